@@ -0,0 +1,283 @@
+use serde::Serialize;
+use std::{
+    fmt, fs,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tempfile::NamedTempFile;
+
+use crate::{
+    archive::{self, UnpackedFile},
+    database::Package,
+    hash,
+    repo::AssetInfo,
+    search_path,
+};
+
+/// Binaries which do not answer `--version` in time are killed.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum Error {
+    AlreadyInstalled { name: String, path: String },
+    BinaryNotFound(Vec<String>),
+    UnknownVersion(PathBuf),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::AlreadyInstalled { name, path } => {
+                write!(f, "{} is already installed at {}", name, path)
+            }
+            Error::BinaryNotFound(names) => write!(
+                f,
+                "Could not find {} in the install path or a prefix, pass its name with --bin-name or its path with --binary",
+                names.join(" or ")
+            ),
+            Error::UnknownVersion(path) => write!(
+                f,
+                "Could not determine the version of {}, pass it with --tag",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Executable which is not managed by GRPM with a repository it probably comes from.
+#[derive(Serialize)]
+pub struct Suggestion {
+    pub binary: String,
+    pub repository: Option<String>,
+}
+
+/// Names the binary of a repository probably has: the repository name and the names its assets
+/// start with, like `bat` for *bat-v0.22.1-x86_64-unknown-linux-musl.tar.gz*.
+pub fn binary_names(repo_name: &str, assets: &[AssetInfo]) -> Vec<String> {
+    let mut names = vec![repo_name.to_string()];
+    for asset in assets {
+        let stem = asset
+            .name
+            .split(['-', '_', '.'])
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        if !stem.is_empty() && !names.contains(&stem) {
+            names.push(stem);
+        }
+    }
+    names
+}
+
+/// Finds the first executable with one of the names in the directories.
+pub fn find_binary(names: &[String], directories: &[PathBuf]) -> Option<PathBuf> {
+    directories
+        .iter()
+        .flat_map(|directory| names.iter().map(move |name| directory.join(name)))
+        .find(|path| search_path::is_executable(path))
+}
+
+/// Returns true if the binary is in one of the directories GRPM installs to.
+pub fn is_in_directories(binary: &Path, directories: &[PathBuf]) -> bool {
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let Some(parent) = binary.parent() else {
+        return false;
+    };
+    let parent = canonical(parent);
+    directories
+        .iter()
+        .any(|directory| canonical(directory) == parent)
+}
+
+/// Runs `<binary> --version` and returns what it printed, `None` if it failed or took too long.
+pub fn version_output(binary: &Path) -> Option<String> {
+    let mut child = Command::new(binary)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    // Read while waiting, a full pipe would block the binary until the timeout
+    let stdout = read_in_background(child.stdout.take()?);
+    let stderr = read_in_background(child.stderr.take()?);
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait().ok()? {
+            Some(status) => break status,
+            None if started.elapsed() > VERSION_TIMEOUT => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            None => thread::sleep(Duration::from_millis(50)),
+        }
+    };
+    if !status.success() {
+        return None;
+    }
+
+    // Some tools print their version on stderr
+    let mut output = stdout.join().ok()??;
+    output.push_str(&stderr.join().ok()??);
+    Some(output)
+}
+
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<Option<String>> {
+    thread::spawn(move || {
+        let mut output = String::new();
+        pipe.read_to_string(&mut output).ok()?;
+        Some(output)
+    })
+}
+
+/// Unpacks a downloaded release asset and returns the path of the file with the given hash in it.
+pub fn find_in_asset(download_path: &Path, sha256: &str) -> Option<String> {
+    let decompressed_file = NamedTempFile::new().ok()?;
+    archive::decompress_file(download_path, decompressed_file.path()).ok()?;
+    let unpack_dir = tempfile::tempdir().ok()?;
+    let unpacked_files =
+        archive::unpacking_archive(decompressed_file.path(), unpack_dir.path(), 0).ok()?;
+    find_file(&unpacked_files, unpack_dir.path(), sha256).map(|file| file.source.to_string())
+}
+
+fn find_file<'a>(
+    files: &'a [UnpackedFile],
+    unpack_dir: &Path,
+    sha256: &str,
+) -> Option<&'a UnpackedFile> {
+    files.iter().find(|file| {
+        hash::sha256_file(&unpack_dir.join(&file.path)).is_ok_and(|actual| actual == sha256)
+    })
+}
+
+/// Returns the executables in the directory which belong to no package.
+/// Binaries named like the binary of a package elsewhere are left out as well.
+pub fn untracked_executables(directory: &Path, packages: &[Package]) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    let mut executables: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| search_path::is_executable(path))
+        .filter(|path| {
+            !packages
+                .iter()
+                .any(|package| path.file_name() == Some(package.binary.as_ref()))
+        })
+        .collect();
+    executables.sort();
+    executables
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::{self, executable};
+
+    #[test]
+    fn version() {
+        let dir = tempfile::tempdir().unwrap();
+        let tool = dir.path().join("tool");
+        executable(&tool, "#!/bin/sh\necho \"tool $1 1.2.3\"\n");
+        assert_eq!(version_output(&tool).unwrap(), "tool --version 1.2.3\n");
+
+        // More output than a pipe buffers
+        let verbose = dir.path().join("verbose");
+        executable(
+            &verbose,
+            "#!/bin/sh\nhead -c 1000000 /dev/zero\necho 1.2.3\n",
+        );
+        let started = Instant::now();
+        assert_eq!(version_output(&verbose).unwrap().len(), 1000006);
+        assert!(started.elapsed() < VERSION_TIMEOUT);
+
+        executable(&tool, "#!/bin/sh\nexit 1\n");
+        assert!(version_output(&tool).is_none());
+    }
+
+    #[test]
+    fn find_in_directories() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        executable(&second.path().join("rg"), "");
+        let directories = vec![first.path().to_path_buf(), second.path().to_path_buf()];
+        let names =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|name| name.to_string()).collect() };
+        assert_eq!(
+            find_binary(&names(&["ripgrep", "rg"]), &directories),
+            Some(second.path().join("rg"))
+        );
+        assert_eq!(find_binary(&names(&["fd"]), &directories), None);
+
+        assert!(is_in_directories(&second.path().join("rg"), &directories));
+        assert!(!is_in_directories(Path::new("/usr/bin/rg"), &directories));
+    }
+
+    #[test]
+    fn names_of_assets() {
+        let assets: Vec<AssetInfo> = [
+            "bat-v0.22.1-x86_64-unknown-linux-musl.tar.gz",
+            "bat_0.22.1_amd64.deb",
+            "batcat.zip",
+        ]
+        .iter()
+        .map(|name| AssetInfo {
+            name: name.to_string(),
+            url: String::new(),
+            size: 0,
+            download_count: 0,
+        })
+        .collect();
+        assert_eq!(binary_names("bat", &assets), vec!["bat", "batcat"]);
+    }
+
+    #[test]
+    fn untracked() {
+        let dir = tempfile::tempdir().unwrap();
+        executable(&dir.path().join("rg"), "");
+        executable(&dir.path().join("fd"), "");
+        executable(&dir.path().join("bat"), "");
+        fs::write(dir.path().join("README"), "").unwrap();
+        let packages = [
+            Package {
+                name: "ripgrep".to_string(),
+                ..test_util::package("rg", dir.path())
+            },
+            test_util::package("bat", Path::new("/opt/work/bin")),
+        ];
+        assert_eq!(
+            untracked_executables(dir.path(), &packages),
+            vec![dir.path().join("fd")]
+        );
+    }
+
+    #[test]
+    fn file_by_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), "a").unwrap();
+        fs::write(dir.path().join("b"), "b").unwrap();
+        let files: Vec<UnpackedFile> = ["a", "b"]
+            .iter()
+            .map(|name| UnpackedFile {
+                path: PathBuf::from(name),
+                source: format!("tool/{}", name),
+                executable: true,
+            })
+            .collect();
+        let sha256 = hash::sha256_file(&dir.path().join("b")).unwrap();
+        assert_eq!(
+            find_file(&files, dir.path(), &sha256).map(|file| file.source.as_str()),
+            Some("tool/b")
+        );
+    }
+}
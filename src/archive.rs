@@ -1,80 +1,528 @@
 use std::{
-    fs::File,
-    io::{copy, BufReader, BufWriter},
-    path::Path,
+    ffi::OsStr,
+    fmt,
+    fs::{self, File},
+    io::{self, copy, BufReader, BufWriter, Read},
+    os::unix::fs::PermissionsExt,
+    path::{Component, Path, PathBuf},
 };
 
 use flate2::bufread::GzDecoder;
 use infer::Type;
-use tar::Archive;
+use tar::{Archive, EntryType};
 
-pub struct TarInfo {
-    pub name: String,
-    pub size: u64,
+/// Largest file accepted in an archive.
+const MAX_ENTRY_SIZE: u64 = 512 * 1024 * 1024;
+/// Largest decompressed archive, guards against decompression bombs.
+const MAX_TOTAL_SIZE: u64 = 1024 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    UnsafePath(String),
+    UnsafeLink { name: String, target: String },
+    UnsupportedEntry { name: String, kind: EntryType },
+    EntryTooLarge { name: String, size: u64 },
+    ArchiveTooLarge,
+    UnsupportedFormat(String),
+    NoBinary,
+    AmbiguousBinary(Vec<String>),
 }
 
-pub fn get_file_type(path: &Path) -> Type {
-    infer::get_from_path(path).unwrap().unwrap()
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::UnsafePath(name) => {
+                write!(f, "Entry {} points outside of the archive", name)
+            }
+            Error::UnsafeLink { name, target } => {
+                write!(
+                    f,
+                    "Link {} to {} points outside of the archive",
+                    name, target
+                )
+            }
+            Error::UnsupportedEntry { name, kind } => {
+                write!(f, "Entry {} has the unsupported type {:?}", name, kind)
+            }
+            Error::EntryTooLarge { name, size } => write!(
+                f,
+                "Entry {} has {} bytes, more than the allowed {} bytes",
+                name, size, MAX_ENTRY_SIZE
+            ),
+            Error::ArchiveTooLarge => write!(
+                f,
+                "Archive exceeds {} bytes when decompressed",
+                MAX_TOTAL_SIZE
+            ),
+            Error::UnsupportedFormat(format) => {
+                write!(f, "Unsupported archive format {}", format)
+            }
+            Error::NoBinary => write!(f, "Archive contains no executable file"),
+            Error::AmbiguousBinary(names) => write!(
+                f,
+                "Archive contains multiple executables ({}), choose one with --bin-name",
+                names.join(", ")
+            ),
+        }
+    }
 }
 
-pub fn decompress_file(source: &Path, destination: &Path) {
-    let source_file_buffer = BufReader::new(File::open(source).unwrap());
-    let mut destination_file_buffer = BufWriter::new(File::create(destination).unwrap());
+impl std::error::Error for Error {}
 
-    let file_type = get_file_type(source);
-    match file_type.mime_type() {
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+/// Options for taking the binary out of an archive.
+#[derive(Default, Clone)]
+pub struct ExtractOptions {
+    /// Number of leading path components removed from every entry.
+    pub strip_components: usize,
+    /// Name the binary is installed as, also used to find it in the archive.
+    pub bin_name: Option<String>,
+    /// Path of the binary in the archive of a previous installation.
+    pub source: Option<String>,
+}
+
+/// Regular file written by `unpacking_archive`.
+pub struct UnpackedFile {
+    /// Path below the destination, after stripping components.
+    pub path: PathBuf,
+    /// Path of the entry in the archive.
+    pub source: String,
+    pub executable: bool,
+}
+
+pub fn get_file_type(path: &Path) -> Result<Option<Type>, Error> {
+    Ok(infer::get_from_path(path)?)
+}
+
+pub fn decompress_file(source: &Path, destination: &Path) -> Result<(), Error> {
+    decompress_file_limited(source, destination, MAX_TOTAL_SIZE)
+}
+
+fn decompress_file_limited(source: &Path, destination: &Path, limit: u64) -> Result<(), Error> {
+    let source_file_buffer = BufReader::new(File::open(source)?);
+    let mut destination_file_buffer = BufWriter::new(File::create(destination)?);
+
+    let file_type = get_file_type(source)?;
+    let mime_type = file_type.map_or("unknown", |file_type| file_type.mime_type());
+    let written = match mime_type {
         "application/gzip" | "application/x-gzip" => {
-            let mut decoder = GzDecoder::new(source_file_buffer);
-            copy(&mut decoder, &mut destination_file_buffer).unwrap();
+            let decoder = GzDecoder::new(source_file_buffer);
+            copy(&mut decoder.take(limit + 1), &mut destination_file_buffer)?
         }
         "application/bzip2" | "application/x-bzip2" => {
-            let mut decoder = bzip2::bufread::BzDecoder::new(source_file_buffer);
-            copy(&mut decoder, &mut destination_file_buffer).unwrap();
+            let decoder = bzip2::bufread::BzDecoder::new(source_file_buffer);
+            copy(&mut decoder.take(limit + 1), &mut destination_file_buffer)?
         }
-        _ => {}
-    }
-}
-
-pub fn get_tar_infos(path: &Path) -> Vec<TarInfo> {
-    let archive_file_buffer = BufReader::new(File::open(path).unwrap());
-    let mut archive = Archive::new(archive_file_buffer);
-    archive
-        .entries()
-        .unwrap()
-        .map(|entry| {
-            let file = entry.unwrap();
-
-            TarInfo {
-                name: file
-                    .header()
-                    .path()
-                    .unwrap()
-                    .into_owned()
-                    .to_str()
-                    .unwrap()
-                    .to_owned(),
-                size: file.header().size().unwrap(),
-            }
-        })
-        .collect()
+        "application/x-tar" => copy(
+            &mut source_file_buffer.take(limit + 1),
+            &mut destination_file_buffer,
+        )?,
+        _ => return Err(Error::UnsupportedFormat(mime_type.to_string())),
+    };
+    if written > limit {
+        return Err(Error::ArchiveTooLarge);
+    }
+    Ok(())
 }
 
-pub fn unpacking_archive(source: &Path, destination: &Path) {
-    let source_file_buffer = BufReader::new(File::open(source).unwrap());
+/// Unpacks the archive into `destination`, validating every entry first.
+/// Entries must stay inside the destination, links must point inside it
+/// and only files, directories and links are accepted.
+/// The first `strip_components` path components are removed, entries without further components are skipped.
+pub fn unpacking_archive(
+    source: &Path,
+    destination: &Path,
+    strip_components: usize,
+) -> Result<Vec<UnpackedFile>, Error> {
+    let source_file_buffer = BufReader::new(File::open(source)?);
     let mut archive = Archive::new(source_file_buffer);
 
-    archive.unpack(destination).unwrap();
+    let mut unpacked_files = Vec::new();
+    let mut total_size = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+        let kind = entry.header().entry_type();
+        if matches!(kind, EntryType::XGlobalHeader | EntryType::XHeader) {
+            continue;
+        }
+
+        let has_parent = Path::new(&name)
+            .components()
+            .any(|component| component == Component::ParentDir);
+        let relative_path = match safe_path(Path::new(&name)) {
+            Some(path) if !has_parent => path,
+            _ => return Err(Error::UnsafePath(name)),
+        };
+        let Some(relative_path) = strip(&relative_path, strip_components) else {
+            continue;
+        };
+        // Earlier entries must not redirect later ones through a link
+        if passes_symlink(destination, &relative_path) {
+            return Err(Error::UnsafePath(name));
+        }
+        let target = destination.join(&relative_path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match kind {
+            EntryType::Regular | EntryType::Continuous => {
+                let size = entry.header().size()?;
+                if size > MAX_ENTRY_SIZE {
+                    return Err(Error::EntryTooLarge { name, size });
+                }
+                total_size += size;
+                if total_size > MAX_TOTAL_SIZE {
+                    return Err(Error::ArchiveTooLarge);
+                }
+                entry.unpack(&target)?;
+                unpacked_files.push(unpacked_file(&target, relative_path, name));
+            }
+            EntryType::Directory => {
+                fs::create_dir_all(&target)?;
+            }
+            EntryType::Symlink => {
+                let link_target = link_name(&entry);
+                let resolved = relative_path
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""))
+                    .join(&link_target);
+                if safe_path(&resolved).is_none() {
+                    return Err(Error::UnsafeLink {
+                        name,
+                        target: link_target,
+                    });
+                }
+                entry.unpack(&target)?;
+            }
+            EntryType::Link => {
+                // Hard link targets are relative to the archive root
+                let link_target = link_name(&entry);
+                let link_source = safe_path(Path::new(&link_target))
+                    .and_then(|path| strip(&path, strip_components));
+                let link_source = match link_source {
+                    Some(path) if !passes_symlink(destination, &path) => path,
+                    _ => {
+                        return Err(Error::UnsafeLink {
+                            name,
+                            target: link_target,
+                        })
+                    }
+                };
+                fs::hard_link(destination.join(link_source), &target)?;
+                unpacked_files.push(unpacked_file(&target, relative_path, name));
+            }
+            _ => return Err(Error::UnsupportedEntry { name, kind }),
+        }
+    }
+    Ok(unpacked_files)
 }
 
-pub fn _unpack_file(file: &File, index: usize, destination: &Path) {
-    let source_file_buffer = BufReader::new(file);
-    let mut archive = Archive::new(source_file_buffer);
-    archive
-        .entries()
-        .unwrap()
-        .nth(index)
-        .unwrap()
-        .unwrap()
-        .unpack_in(destination)
-        .unwrap();
+/// Chooses the binary to install from the unpacked files.
+/// A file named like one of `names` wins, otherwise the only file or the only executable is chosen.
+pub fn select_binary<'a>(
+    files: &'a [UnpackedFile],
+    names: &[&str],
+) -> Result<&'a UnpackedFile, Error> {
+    for name in names {
+        let matching: Vec<&UnpackedFile> = files
+            .iter()
+            .filter(|file| file.path.file_name() == Some(OsStr::new(name)))
+            .collect();
+        if let [file] = matching.as_slice() {
+            return Ok(file);
+        }
+    }
+    if let [file] = files {
+        return Ok(file);
+    }
+    let executables: Vec<&UnpackedFile> = files.iter().filter(|file| file.executable).collect();
+    match executables.as_slice() {
+        [file] => Ok(file),
+        [] => Err(Error::NoBinary),
+        _ => Err(Error::AmbiguousBinary(
+            executables
+                .iter()
+                .map(|file| file.source.to_string())
+                .collect(),
+        )),
+    }
+}
+
+fn unpacked_file(target: &Path, path: PathBuf, source: String) -> UnpackedFile {
+    let executable = fs::metadata(target)
+        .map(|metadata| metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false);
+    UnpackedFile {
+        path,
+        source,
+        executable,
+    }
+}
+
+/// Removes the first `count` components, returns `None` if nothing is left.
+fn strip(path: &Path, count: usize) -> Option<PathBuf> {
+    let components: Vec<Component> = path.components().collect();
+    if components.len() <= count {
+        return None;
+    }
+    Some(components[count..].iter().collect())
+}
+
+/// Normalizes a relative path, returns `None` if it is absolute or leaves its root through `..`.
+fn safe_path(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+/// Returns true if the path or one of its parents below `root` is an already unpacked symlink.
+fn passes_symlink(root: &Path, relative_path: &Path) -> bool {
+    relative_path
+        .ancestors()
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .any(|ancestor| {
+            fs::symlink_metadata(root.join(ancestor))
+                .map(|metadata| metadata.file_type().is_symlink())
+                .unwrap_or(false)
+        })
+}
+
+fn link_name<R: Read>(entry: &tar::Entry<R>) -> String {
+    entry
+        .link_name_bytes()
+        .map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Writes the name without the validation of `tar::Builder`, like a crafted archive would.
+    fn header(name: &str, kind: EntryType, size: u64) -> tar::Header {
+        let mut header = tar::Header::new_old();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_entry_type(kind);
+        header.set_size(size);
+        header.set_mode(0o755);
+        header
+    }
+
+    fn link(name: &str, kind: EntryType, target: &str) -> tar::Header {
+        let mut header = header(name, kind, 0);
+        header.as_old_mut().linkname[..target.len()].copy_from_slice(target.as_bytes());
+        header
+    }
+
+    fn unpack(
+        headers: Vec<(tar::Header, &[u8])>,
+    ) -> (tempfile::TempDir, Result<Vec<UnpackedFile>, Error>) {
+        unpack_stripped(headers, 0)
+    }
+
+    fn unpack_stripped(
+        headers: Vec<(tar::Header, &[u8])>,
+        strip_components: usize,
+    ) -> (tempfile::TempDir, Result<Vec<UnpackedFile>, Error>) {
+        let dir = tempfile::tempdir().unwrap();
+        let archive_path = dir.path().join("archive.tar");
+        let mut builder = tar::Builder::new(File::create(&archive_path).unwrap());
+        for (mut header, data) in headers {
+            header.set_cksum();
+            builder.append(&header, data).unwrap();
+        }
+        builder.finish().unwrap();
+
+        let destination = dir.path().join("unpacked");
+        fs::create_dir(&destination).unwrap();
+        let result = unpacking_archive(&archive_path, &destination, strip_components);
+        (dir, result)
+    }
+
+    fn unpacked(path: &str, executable: bool) -> UnpackedFile {
+        UnpackedFile {
+            path: PathBuf::from(path),
+            source: format!("tool-v1.0.0/{}", path),
+            executable,
+        }
+    }
+
+    #[test]
+    fn regular_files() {
+        let (dir, result) = unpack(vec![
+            (header("tool/", EntryType::Directory, 0), &[]),
+            (header("tool/bin", EntryType::Regular, 6), b"binary"),
+            (link("tool/link", EntryType::Symlink, "bin"), &[]),
+            (link("tool/hard", EntryType::Link, "tool/bin"), &[]),
+        ]);
+        result.unwrap();
+        let unpacked = dir.path().join("unpacked").join("tool");
+        assert_eq!(fs::read_to_string(unpacked.join("bin")).unwrap(), "binary");
+        assert_eq!(fs::read_to_string(unpacked.join("link")).unwrap(), "binary");
+        assert_eq!(fs::read_to_string(unpacked.join("hard")).unwrap(), "binary");
+    }
+
+    #[test]
+    fn strip_components() {
+        let mut readme = header("tool-v1.0.0/README.md", EntryType::Regular, 6);
+        readme.set_mode(0o644);
+        let (dir, result) = unpack_stripped(
+            vec![
+                (header("tool-v1.0.0/", EntryType::Directory, 0), &[]),
+                (header("tool-v1.0.0/tool", EntryType::Regular, 6), b"binary"),
+                (readme, b"readme"),
+                (
+                    link("tool-v1.0.0/hard", EntryType::Link, "tool-v1.0.0/tool"),
+                    &[],
+                ),
+            ],
+            1,
+        );
+        let files = result.unwrap();
+        let unpacked = dir.path().join("unpacked");
+        assert_eq!(fs::read_to_string(unpacked.join("tool")).unwrap(), "binary");
+        assert_eq!(fs::read_to_string(unpacked.join("hard")).unwrap(), "binary");
+        assert_eq!(files.len(), 3);
+        assert_eq!(files[0].path, PathBuf::from("tool"));
+        assert_eq!(files[0].source, "tool-v1.0.0/tool");
+        assert!(files[0].executable);
+        assert!(!files[1].executable);
+    }
+
+    #[test]
+    fn select_single_executable() {
+        let files = vec![unpacked("README.md", false), unpacked("tool", true)];
+        assert_eq!(
+            select_binary(&files, &[]).unwrap().path,
+            PathBuf::from("tool")
+        );
+    }
+
+    #[test]
+    fn select_by_name() {
+        let files = vec![unpacked("tool", true), unpacked("tool-helper", true)];
+        assert!(matches!(
+            select_binary(&files, &[]),
+            Err(Error::AmbiguousBinary(_))
+        ));
+        let binary = select_binary(&files, &["renamed", "tool-helper"]).unwrap();
+        assert_eq!(binary.path, PathBuf::from("tool-helper"));
+    }
+
+    #[test]
+    fn select_without_executable() {
+        let files = vec![unpacked("README.md", false), unpacked("LICENSE", false)];
+        assert!(matches!(select_binary(&files, &[]), Err(Error::NoBinary)));
+    }
+
+    #[test]
+    fn absolute_path() {
+        let (_dir, result) = unpack(vec![(
+            header("/tmp/grpm-evil", EntryType::Regular, 4),
+            b"evil",
+        )]);
+        assert!(matches!(result, Err(Error::UnsafePath(_))));
+    }
+
+    #[test]
+    fn parent_path() {
+        let (dir, result) = unpack(vec![(
+            header("tool/../../evil", EntryType::Regular, 4),
+            b"evil",
+        )]);
+        assert!(matches!(result, Err(Error::UnsafePath(_))));
+        assert!(!dir.path().join("evil").exists());
+        let (_dir, result) = unpack(vec![(
+            header("tool/../evil", EntryType::Regular, 4),
+            b"evil",
+        )]);
+        assert!(matches!(result, Err(Error::UnsafePath(_))));
+    }
+
+    #[test]
+    fn symlink_outside() {
+        let (_dir, result) = unpack(vec![(
+            link("evil", EntryType::Symlink, "../../etc/passwd"),
+            &[],
+        )]);
+        assert!(matches!(result, Err(Error::UnsafeLink { .. })));
+        let (_dir, result) = unpack(vec![(link("evil", EntryType::Symlink, "/etc/passwd"), &[])]);
+        assert!(matches!(result, Err(Error::UnsafeLink { .. })));
+    }
+
+    #[test]
+    fn write_through_symlink() {
+        let (dir, result) = unpack(vec![
+            (header("sub/", EntryType::Directory, 0), &[]),
+            (link("dir", EntryType::Symlink, "sub"), &[]),
+            (header("dir/evil", EntryType::Regular, 4), b"evil"),
+        ]);
+        assert!(matches!(result, Err(Error::UnsafePath(_))));
+        assert!(!dir
+            .path()
+            .join("unpacked")
+            .join("sub")
+            .join("evil")
+            .exists());
+    }
+
+    #[test]
+    fn hardlink_outside() {
+        let (_dir, result) = unpack(vec![(link("evil", EntryType::Link, "../archive.tar"), &[])]);
+        assert!(matches!(result, Err(Error::UnsafeLink { .. })));
+    }
+
+    #[test]
+    fn device_file() {
+        let (_dir, result) = unpack(vec![(header("null", EntryType::Char, 0), &[])]);
+        assert!(matches!(result, Err(Error::UnsupportedEntry { .. })));
+        let (_dir, result) = unpack(vec![(header("fifo", EntryType::Fifo, 0), &[])]);
+        assert!(matches!(result, Err(Error::UnsupportedEntry { .. })));
+    }
+
+    #[test]
+    fn oversized_entry() {
+        // Only the header claims the size, the check happens before reading the content
+        let mut oversized = header("bomb", EntryType::Regular, 0);
+        oversized.set_size(MAX_ENTRY_SIZE + 1);
+        let (_dir, result) = unpack(vec![(oversized, &[])]);
+        assert!(matches!(result, Err(Error::EntryTooLarge { .. })));
+    }
+
+    #[test]
+    fn decompression_bomb() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("bomb.gz");
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(&source).unwrap(),
+            flate2::Compression::best(),
+        );
+        io::copy(&mut io::repeat(0).take(1024 * 1024), &mut encoder).unwrap();
+        encoder.finish().unwrap();
+
+        let result = decompress_file_limited(&source, &dir.path().join("bomb.tar"), 1024);
+        assert!(matches!(result, Err(Error::ArchiveTooLarge)));
+        assert!(decompress_file(&source, &dir.path().join("bomb.tar")).is_ok());
+    }
 }
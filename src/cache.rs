@@ -0,0 +1,523 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    hash,
+    print::status,
+    privilege,
+    repo::{self, AssetInfo},
+    version,
+};
+
+/// Holds the real tag of a cached release.
+const TAG_FILE: &str = ".tag";
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct CachedResponse {
+    pub etag: String,
+    pub body: String,
+}
+
+/// Cached API responses. Those of a repository are stored in *repos/<owner>/<repo>/*,
+/// so they can be removed together.
+pub struct HttpCache {
+    path: PathBuf,
+    read_only: bool,
+}
+
+impl HttpCache {
+    pub fn new(path: impl AsRef<Path>) -> HttpCache {
+        HttpCache {
+            path: path.as_ref().to_path_buf(),
+            read_only: false,
+        }
+    }
+
+    /// Cache which serves existing responses but never stores new ones, used for dry runs.
+    pub fn read_only(path: impl AsRef<Path>) -> HttpCache {
+        HttpCache {
+            path: path.as_ref().to_path_buf(),
+            read_only: true,
+        }
+    }
+
+    pub fn get(&self, url: &str) -> Option<CachedResponse> {
+        let content = fs::read_to_string(self.entry_path(url)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn put(&self, url: &str, response: &CachedResponse) -> io::Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let entry_path = self.entry_path(url);
+        if let Some(parent) = entry_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string(response)?;
+        fs::write(entry_path, content)
+    }
+
+    /// Removes the cached responses of a repository and its releases, returns the removed files.
+    pub fn remove_repo(&self, full_name: &str, dry_run: bool) -> io::Result<Vec<PathBuf>> {
+        let repo_path = self.repo_path(full_name);
+        let entries = match fs::read_dir(&repo_path) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+        let mut removed: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+        removed.sort();
+        if !dry_run {
+            fs::remove_dir_all(&repo_path)?;
+        }
+        Ok(removed)
+    }
+
+    fn entry_path(&self, url: &str) -> PathBuf {
+        let path = url.trim_start_matches("https://api.github.com/");
+        match split_repo(path) {
+            Some((full_name, rest)) => {
+                let name = entry_name(rest.trim_start_matches('/'));
+                let name = if name.is_empty() { "repo" } else { &name };
+                self.repo_path(full_name).join(format!("{}.json", name))
+            }
+            None => self.path.join(format!("{}.json", entry_name(path))),
+        }
+    }
+
+    /// GitHub treats repository names case-insensitively.
+    fn repo_path(&self, full_name: &str) -> PathBuf {
+        self.path.join("repos").join(full_name.to_lowercase())
+    }
+}
+
+/// Downloaded release assets, stored as *<owner>/<repo>/<tag>/<asset>*.
+/// The real tag is kept in a *.tag* file, the directory name has `/` replaced.
+pub struct DownloadCache {
+    path: PathBuf,
+}
+
+impl DownloadCache {
+    pub fn new(path: impl AsRef<Path>) -> DownloadCache {
+        DownloadCache {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn get(&self, full_name: &str, tag: &str, asset: &str) -> Option<PathBuf> {
+        let release_path = self.release_path(full_name, tag);
+        // Another tag which only differs by `/` and `_` is stored in the same directory
+        if release_tag(&release_path)? != tag {
+            return None;
+        }
+        let path = release_path.join(asset);
+        path.is_file().then_some(path)
+    }
+
+    /// Copies a downloaded asset into the cache. The copy is renamed into place,
+    /// so an interrupted copy is never served.
+    pub fn put(&self, full_name: &str, tag: &str, asset: &str, file: &Path) -> io::Result<()> {
+        let release_path = self.release_path(full_name, tag);
+        if release_tag(&release_path).is_some_and(|cached_tag| cached_tag != tag) {
+            fs::remove_dir_all(&release_path)?;
+        }
+        fs::create_dir_all(&release_path)?;
+        fs::write(release_path.join(TAG_FILE), tag)?;
+        let partial_path = release_path.join(format!(".{}.partial", asset));
+        fs::copy(file, &partial_path)?;
+        fs::rename(&partial_path, release_path.join(asset))
+    }
+
+    /// Copies the asset from the cache to the destination, or downloads it and keeps a copy.
+    /// A cached copy which does not match `sha256` is removed and downloaded again.
+    pub fn fetch(
+        &self,
+        full_name: &str,
+        tag: &str,
+        asset: &AssetInfo,
+        sha256: Option<&str>,
+        destination: &Path,
+        offline: bool,
+    ) -> Result<(), repo::Error> {
+        if let Some(cached_path) = self.get(full_name, tag, &asset.name) {
+            let intact = sha256.is_none_or(|sha256| {
+                hash::sha256_file(&cached_path)
+                    .is_ok_and(|actual| actual.eq_ignore_ascii_case(sha256))
+            });
+            if intact {
+                status!("Using cached {}", asset.name);
+                fs::copy(cached_path, destination)?;
+                return Ok(());
+            }
+            eprintln!(
+                "Cached {} does not match its recorded checksum, removing it",
+                asset.name
+            );
+            fs::remove_file(cached_path)?;
+        }
+        if offline {
+            let resource = format!("Asset {} of {} {}", asset.name, full_name, tag);
+            return Err(repo::Error::NotCached(resource));
+        }
+
+        repo::download_asset(asset, destination)?;
+        // The system-wide cache is skipped by users who may not write it
+        if !privilege::is_writable(&self.path) {
+            return Ok(());
+        }
+        if let Err(error) = self.put(full_name, tag, &asset.name, destination) {
+            eprintln!("Could not cache {}: {}", asset.name, error);
+        }
+        Ok(())
+    }
+
+    /// Returns the cached tags of a repository with the names of their assets.
+    pub fn releases(&self, full_name: &str) -> Vec<(String, Vec<String>)> {
+        let Ok(entries) = fs::read_dir(self.repo_path(full_name)) else {
+            return Vec::new();
+        };
+        let mut releases: Vec<(String, Vec<String>)> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let tag = release_tag(&entry.path())?;
+                let mut assets: Vec<String> = fs::read_dir(entry.path())
+                    .ok()?
+                    .flatten()
+                    .filter_map(|asset| asset.file_name().to_str().map(str::to_string))
+                    .filter(|asset| !asset.starts_with('.'))
+                    .collect();
+                assets.sort();
+                (!assets.is_empty()).then_some((tag, assets))
+            })
+            .collect();
+        releases.sort();
+        releases
+    }
+
+    /// Returns the newest cached release before the installed one.
+    pub fn previous_release(
+        &self,
+        full_name: &str,
+        installed_tag: &str,
+    ) -> Option<(String, Vec<String>)> {
+        self.older_releases(full_name, installed_tag)
+            .into_iter()
+            .next()
+    }
+
+    /// Keeps the `keep` newest releases before the installed one and removes the older ones.
+    /// Newer releases are only cached after a rollback and are kept as well.
+    pub fn prune(&self, full_name: &str, installed_tag: &str, keep: usize) -> io::Result<()> {
+        for (tag, _) in self
+            .older_releases(full_name, installed_tag)
+            .into_iter()
+            .skip(keep)
+        {
+            fs::remove_dir_all(self.release_path(full_name, &tag))?;
+        }
+        Ok(())
+    }
+
+    /// Removes the cached assets of a repository, returns the removed files.
+    pub fn remove_repo(&self, full_name: &str, dry_run: bool) -> io::Result<Vec<PathBuf>> {
+        let repo_path = self.repo_path(full_name);
+        let removed = self
+            .releases(full_name)
+            .into_iter()
+            .flat_map(|(tag, assets)| {
+                let release_path = self.release_path(full_name, &tag);
+                assets
+                    .into_iter()
+                    .map(move |asset| release_path.join(asset))
+            })
+            .collect();
+        if !dry_run && repo_path.exists() {
+            fs::remove_dir_all(&repo_path)?;
+        }
+        Ok(removed)
+    }
+
+    /// Cached releases before the installed one, newest first. Tags without a version are left out.
+    fn older_releases(&self, full_name: &str, installed_tag: &str) -> Vec<(String, Vec<String>)> {
+        let mut releases: Vec<(String, Vec<String>)> = self
+            .releases(full_name)
+            .into_iter()
+            .filter(|(tag, _)| version::compare_tags(tag, installed_tag) == Some(Ordering::Less))
+            .collect();
+        releases.sort_by(|(left, _), (right, _)| {
+            version::compare_tags(right, left).unwrap_or(Ordering::Equal)
+        });
+        releases
+    }
+
+    /// GitHub treats repository names case-insensitively.
+    fn repo_path(&self, full_name: &str) -> PathBuf {
+        full_name
+            .to_lowercase()
+            .split('/')
+            .fold(self.path.clone(), |path, component| path.join(component))
+    }
+
+    fn release_path(&self, full_name: &str, tag: &str) -> PathBuf {
+        self.repo_path(full_name).join(tag.replace('/', "_"))
+    }
+}
+
+/// Tag of a cached release, releases cached before the tag file was written are named by it.
+fn release_tag(release_path: &Path) -> Option<String> {
+    match fs::read_to_string(release_path.join(TAG_FILE)) {
+        Ok(tag) => Some(tag),
+        Err(_) if release_path.is_dir() => release_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(str::to_string),
+        Err(_) => None,
+    }
+}
+
+/// Splits `repos/<owner>/<repo>/releases` into `<owner>/<repo>` and `/releases`.
+fn split_repo(path: &str) -> Option<(&str, &str)> {
+    let repo_url = path.strip_prefix("repos/")?;
+    let (owner, rest) = repo_url.split_once('/')?;
+    let repo_end = rest.find(['/', '?']).unwrap_or(rest.len());
+    (!owner.is_empty() && repo_end > 0).then(|| repo_url.split_at(owner.len() + 1 + repo_end))
+}
+
+fn entry_name(path: &str) -> String {
+    path.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn put_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = HttpCache::new(dir.path());
+        let url = "https://api.github.com/repos/owner/repo";
+        let response = CachedResponse {
+            etag: "\"abc\"".to_string(),
+            body: "{}".to_string(),
+        };
+        cache.put(url, &response).unwrap();
+        assert_eq!(cache.get(url).as_ref(), Some(&response));
+        assert!(dir.path().join("repos/owner/repo/repo.json").exists());
+
+        let url = "https://api.github.com/search/repositories?q=rg";
+        cache.put(url, &response).unwrap();
+        assert_eq!(cache.get(url), Some(response));
+        assert!(dir.path().join("search_repositories_q_rg.json").exists());
+    }
+
+    #[test]
+    fn remove_repo() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = HttpCache::new(dir.path());
+        let response = CachedResponse {
+            etag: "\"abc\"".to_string(),
+            body: "{}".to_string(),
+        };
+        for url in [
+            "https://api.github.com/repos/Owner/repo",
+            "https://api.github.com/repos/owner/repo/releases/latest",
+            "https://api.github.com/repos/owner/repo/releases?per_page=5",
+            "https://api.github.com/repos/owner/repository",
+            "https://api.github.com/repos/owner/repo-cli",
+            "https://api.github.com/repos/owner/repo-cli/releases/latest",
+        ] {
+            cache.put(url, &response).unwrap();
+        }
+        assert_eq!(cache.remove_repo("owner/repo", true).unwrap().len(), 3);
+        assert_eq!(cache.remove_repo("owner/repo", false).unwrap().len(), 3);
+        for url in [
+            "https://api.github.com/repos/owner/repository",
+            "https://api.github.com/repos/owner/repo-cli",
+            "https://api.github.com/repos/owner/repo-cli/releases/latest",
+        ] {
+            assert!(cache.get(url).is_some());
+        }
+        assert!(cache.remove_repo("owner/repo", false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn read_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = "https://api.github.com/repos/owner/repo";
+        let response = CachedResponse {
+            etag: "\"abc\"".to_string(),
+            body: "{}".to_string(),
+        };
+        HttpCache::read_only(dir.path())
+            .put(url, &response)
+            .unwrap();
+        assert!(HttpCache::new(dir.path()).get(url).is_none());
+
+        HttpCache::new(dir.path()).put(url, &response).unwrap();
+        assert_eq!(HttpCache::read_only(dir.path()).get(url), Some(response));
+    }
+
+    #[test]
+    fn downloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DownloadCache::new(dir.path().join("downloads"));
+        let asset = dir.path().join("rg.tar.gz");
+        fs::write(&asset, "asset").unwrap();
+        assert!(cache.get("owner/repo", "v1.0.0", "rg.tar.gz").is_none());
+
+        cache
+            .put("Owner/Repo", "v1.0.0", "rg.tar.gz", &asset)
+            .unwrap();
+        cache
+            .put("owner/repo", "v1.1.0", "rg.tar.gz", &asset)
+            .unwrap();
+        let cached = cache.get("owner/repo", "v1.0.0", "rg.tar.gz").unwrap();
+        assert_eq!(
+            cached,
+            dir.path().join("downloads/owner/repo/v1.0.0/rg.tar.gz")
+        );
+        assert_eq!(fs::read_to_string(cached).unwrap(), "asset");
+        assert_eq!(
+            cache.releases("owner/repo"),
+            vec![
+                ("v1.0.0".to_string(), vec!["rg.tar.gz".to_string()]),
+                ("v1.1.0".to_string(), vec!["rg.tar.gz".to_string()]),
+            ]
+        );
+
+        assert_eq!(cache.remove_repo("owner/repo", true).unwrap().len(), 2);
+        assert_eq!(cache.releases("owner/repo").len(), 2);
+        cache.remove_repo("owner/repo", false).unwrap();
+        assert!(cache.releases("owner/repo").is_empty());
+    }
+
+    fn cache_release(cache: &DownloadCache, tag: &str, asset: &str, content: &str) {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), content).unwrap();
+        cache.put("owner/repo", tag, asset, file.path()).unwrap();
+    }
+
+    fn asset_info(name: &str) -> AssetInfo {
+        AssetInfo {
+            name: name.to_string(),
+            url: format!("https://example.invalid/{}", name),
+            size: 0,
+            download_count: 0,
+        }
+    }
+
+    #[test]
+    fn tags_with_slashes() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DownloadCache::new(dir.path());
+        cache_release(&cache, "release/1.2", "rg.tar.gz", "asset");
+        assert!(cache
+            .get("owner/repo", "release/1.2", "rg.tar.gz")
+            .is_some());
+        assert!(cache
+            .get("owner/repo", "release_1.2", "rg.tar.gz")
+            .is_none());
+        assert_eq!(
+            cache.releases("owner/repo"),
+            vec![("release/1.2".to_string(), vec!["rg.tar.gz".to_string()])]
+        );
+
+        cache_release(&cache, "release_1.2", "rg.tar.gz", "other");
+        assert!(cache
+            .get("owner/repo", "release/1.2", "rg.tar.gz")
+            .is_none());
+        assert_eq!(cache.releases("owner/repo")[0].0, "release_1.2");
+    }
+
+    #[test]
+    fn rollback_release() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DownloadCache::new(dir.path());
+        for tag in ["v1.9.0", "v1.10.0-rc1", "v1.10.0", "v1.11.0", "nightly"] {
+            cache_release(&cache, tag, "rg.tar.gz", tag);
+        }
+        let previous = |installed| {
+            cache
+                .previous_release("owner/repo", installed)
+                .map(|(tag, _)| tag)
+        };
+        assert_eq!(previous("v1.10.0").as_deref(), Some("v1.10.0-rc1"));
+        assert_eq!(previous("1.10.0-rc1").as_deref(), Some("v1.9.0"));
+        assert_eq!(previous("v1.9.0"), None);
+        assert_eq!(previous("nightly"), None);
+    }
+
+    #[test]
+    fn prune() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DownloadCache::new(dir.path());
+        for tag in ["v1.0.0", "v1.1.0", "v1.2.0", "v1.3.0", "v1.4.0"] {
+            cache_release(&cache, tag, "rg.tar.gz", tag);
+        }
+        cache.prune("owner/repo", "v1.3.0", 1).unwrap();
+        let tags: Vec<String> = cache
+            .releases("owner/repo")
+            .into_iter()
+            .map(|(tag, _)| tag)
+            .collect();
+        assert_eq!(tags, vec!["v1.2.0", "v1.3.0", "v1.4.0"]);
+    }
+
+    #[test]
+    fn fetch_offline() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DownloadCache::new(dir.path().join("downloads"));
+        let destination = dir.path().join("download");
+        let asset = asset_info("rg.tar.gz");
+        assert!(matches!(
+            cache.fetch("owner/repo", "v1.0.0", &asset, None, &destination, true),
+            Err(repo::Error::NotCached(_))
+        ));
+
+        cache_release(&cache, "v1.0.0", "rg.tar.gz", "hello");
+        let sha256 = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        cache
+            .fetch(
+                "owner/repo",
+                "v1.0.0",
+                &asset,
+                Some(sha256),
+                &destination,
+                true,
+            )
+            .unwrap();
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "hello");
+
+        // A cached copy which differs from the recorded checksum is never used
+        let other = "0".repeat(64);
+        assert!(matches!(
+            cache.fetch(
+                "owner/repo",
+                "v1.0.0",
+                &asset,
+                Some(&other),
+                &destination,
+                true
+            ),
+            Err(repo::Error::NotCached(_))
+        ));
+        assert!(cache.get("owner/repo", "v1.0.0", "rg.tar.gz").is_none());
+    }
+
+    #[test]
+    fn get_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = HttpCache::new(dir.path());
+        assert!(cache
+            .get("https://api.github.com/repos/owner/repo")
+            .is_none());
+    }
+}
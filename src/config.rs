@@ -1,52 +1,335 @@
-use serde::{Deserialize, Deserializer};
 use std::{
-    fs,
+    collections::HashMap,
+    env, fmt, fs, io,
     path::{Path, PathBuf},
+    process::Command,
+    sync::OnceLock,
 };
-use toml;
 
-#[derive(Deserialize)]
+use toml_edit::{Document, Item, Table, Value};
+
+use crate::privilege;
+
+static SYSTEM_MODE: OnceLock<bool> = OnceLock::new();
+
+const SYSTEM_CONFIG_PATH: &str = "/etc/grpm";
+const SYSTEM_DATABASE_PATH: &str = "/var/lib/grpm";
+const SYSTEM_CACHE_PATH: &str = "/var/cache/grpm";
+const SYSTEM_INSTALL_PATH: &str = "/usr/local/bin";
+const SYSTEM_DATA_PATH: &str = "/usr/local/share";
+
+/// Keys accepted by `grpm config get` and `grpm config set`.
+pub const KEYS: [&str; 4] = ["token", "install_path", "jobs", "prefixes.<name>"];
+
+/// Configuration read from config.toml, every field is optional.
 pub struct Config {
     pub token: Option<String>,
-    #[serde(deserialize_with = "string_to_path")]
     pub install_path: PathBuf,
+    pub jobs: usize,
+    pub prefixes: HashMap<String, PathBuf>,
+}
+
+/// Problem with a single value of the config file.
+#[derive(PartialEq, Debug)]
+pub struct FieldError {
+    pub key: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(key: &str, message: &str) -> FieldError {
+        FieldError {
+            key: key.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml_edit::TomlError),
+    Invalid(PathBuf, Vec<FieldError>),
+    UnknownPrefix(String),
+    UnknownKey(String),
+    Editor(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            Error::Parse(path, error) => write!(f, "{}: {}", path.display(), error),
+            Error::Invalid(path, errors) => {
+                write!(f, "{} is invalid", path.display())?;
+                for error in errors {
+                    write!(f, "\n  {}", error)?;
+                }
+                Ok(())
+            }
+            Error::UnknownPrefix(prefix) => write!(f, "Prefix '{}' is not configured", prefix),
+            Error::UnknownKey(key) => write!(
+                f,
+                "Unknown key '{}', known keys are {}",
+                key,
+                KEYS.join(", ")
+            ),
+            Error::Editor(editor) => write!(f, "Editor {} failed", editor),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn default_jobs() -> usize {
+    4
 }
 
-fn string_to_path<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let buf = String::deserialize(deserializer)?;
-    let path = PathBuf::from(shellexpand::tilde(&buf).as_ref());
-    Ok(path)
+fn expand_path(path: &str) -> PathBuf {
+    PathBuf::from(shellexpand::tilde(path).as_ref())
+}
+
+/// The XDG spec requires absolute paths, an empty or relative `$XDG_BIN_HOME` is ignored.
+fn user_install_path(xdg_bin_home: Option<&str>) -> PathBuf {
+    xdg_bin_home
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| expand_path("~/.local/bin"))
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
-            install_path: Config::get_config_path(),
+            install_path: Config::get_default_install_path(),
             token: None,
+            jobs: default_jobs(),
+            prefixes: HashMap::new(),
         }
     }
 }
 
 impl Config {
-    pub fn new() -> Self {
-        Config::create_default_folders();
+    /// Loads the configuration. In system mode the shared paths below /etc, /var/lib and /var/cache are used.
+    pub fn new(system: bool) -> Result<Self, Error> {
+        Config::set_system_mode(system);
+        Config::create_default_folders()?;
+        Config::load()
+    }
+
+    /// Loads the config file without creating any folders.
+    pub fn load() -> Result<Self, Error> {
         let config_path = Config::get_config_path();
-        if config_path.exists() {
-            let content = fs::read_to_string(config_path).unwrap();
-            let config: Config = toml::from_str(&content).unwrap();
-            return config;
+        let document = Config::read_document(&config_path)?;
+        Config::from_document(&document).map_err(|errors| Error::Invalid(config_path, errors))
+    }
+
+    /// Only the first call decides the mode.
+    pub fn set_system_mode(system: bool) {
+        let _ = SYSTEM_MODE.set(system);
+    }
+
+    /// Reads the config file, keeping its comments and formatting. A missing file is empty.
+    pub fn read_document(path: &Path) -> Result<Document, Error> {
+        if !path.exists() {
+            return Ok(Document::new());
+        }
+        let content =
+            fs::read_to_string(path).map_err(|error| Error::Io(path.to_path_buf(), error))?;
+        content
+            .parse::<Document>()
+            .map_err(|error| Error::Parse(path.to_path_buf(), error))
+    }
+
+    /// Builds the configuration from a config file, collecting an error for every invalid value.
+    pub fn from_document(document: &Document) -> Result<Config, Vec<FieldError>> {
+        let mut config = Config::default();
+        let mut errors = Vec::new();
+        for (key, item) in document.iter() {
+            match key {
+                "token" => match item.as_str() {
+                    Some(token) => config.token = Some(token.to_string()),
+                    None => errors.push(FieldError::new(key, "expected a string")),
+                },
+                "install_path" => match item.as_str() {
+                    Some(path) => config.install_path = expand_path(path),
+                    None => errors.push(FieldError::new(key, "expected a path as string")),
+                },
+                "jobs" => match item.as_integer().filter(|jobs| *jobs > 0) {
+                    Some(jobs) => config.jobs = jobs as usize,
+                    None => errors.push(FieldError::new(key, "expected a positive integer")),
+                },
+                "prefixes" => match item.as_table_like() {
+                    Some(prefixes) => {
+                        for (name, path) in prefixes.iter() {
+                            match path.as_str() {
+                                Some(path) => {
+                                    config.prefixes.insert(name.to_string(), expand_path(path));
+                                }
+                                None => errors.push(FieldError::new(
+                                    &format!("prefixes.{}", name),
+                                    "expected a path as string",
+                                )),
+                            }
+                        }
+                    }
+                    None => errors.push(FieldError::new(key, "expected a table of paths")),
+                },
+                _ => errors.push(FieldError::new(key, "unknown key")),
+            }
+        }
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
         }
-        Config::default()
     }
-    fn create_default_folders() {
-        fs::create_dir_all(Config::get_config_base_path()).unwrap();
-        fs::create_dir_all(Config::get_database_base_path()).unwrap();
+
+    /// Returns the effective value of a key, `None` if it is not set and has no default.
+    pub fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        let value = match key {
+            "token" => self.token.clone(),
+            "install_path" => Some(self.install_path.to_string_lossy().to_string()),
+            "jobs" => Some(self.jobs.to_string()),
+            _ => match key.strip_prefix("prefixes.") {
+                Some(name) => self
+                    .prefixes
+                    .get(name)
+                    .map(|path| path.to_string_lossy().to_string()),
+                None => return Err(Error::UnknownKey(key.to_string())),
+            },
+        };
+        Ok(value)
     }
 
-    fn get_config_path() -> PathBuf {
+    /// Returns all effective values, the token is hidden.
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let mut entries = vec![
+            (
+                "token".to_string(),
+                match self.token {
+                    Some(_) => "<hidden>".to_string(),
+                    None => String::new(),
+                },
+            ),
+            (
+                "install_path".to_string(),
+                self.install_path.to_string_lossy().to_string(),
+            ),
+            ("jobs".to_string(), self.jobs.to_string()),
+        ];
+        let mut prefixes: Vec<(&String, &PathBuf)> = self.prefixes.iter().collect();
+        prefixes.sort();
+        for (name, path) in prefixes {
+            entries.push((
+                format!("prefixes.{}", name),
+                path.to_string_lossy().to_string(),
+            ));
+        }
+        entries
+    }
+
+    /// Sets a key in the config file, keeping comments and the formatting of everything else.
+    /// Nothing is written if the result would be invalid.
+    pub fn set(key: &str, new_value: &str) -> Result<(), Error> {
+        let config_path = Config::get_config_path();
+        let mut document = Config::read_document(&config_path)?;
+        set_value(&mut document, key, new_value)?;
+        Config::from_document(&document)
+            .map_err(|errors| Error::Invalid(config_path.clone(), errors))?;
+
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent).map_err(|error| Error::Io(parent.to_path_buf(), error))?;
+        }
+        fs::write(&config_path, document.to_string()).map_err(|error| Error::Io(config_path, error))
+    }
+
+    /// Opens the config file in `$VISUAL` or `$EDITOR` and validates it afterwards.
+    pub fn edit() -> Result<(), Error> {
+        let config_path = Config::get_config_path();
+        let editor = env::var("VISUAL")
+            .or_else(|_| env::var("EDITOR"))
+            .unwrap_or_else(|_| "vi".to_string());
+        let mut command = editor.split_whitespace();
+        let program = command
+            .next()
+            .ok_or_else(|| Error::Editor(editor.to_string()))?;
+        let status = Command::new(program)
+            .args(command)
+            .arg(&config_path)
+            .status()
+            .map_err(|error| Error::Io(PathBuf::from(program), error))?;
+        if !status.success() {
+            return Err(Error::Editor(editor));
+        }
+        Config::validate()
+    }
+
+    /// Fails with all invalid values of the config file.
+    pub fn validate() -> Result<(), Error> {
+        Config::load().map(|_| ())
+    }
+
+    /// Returns the directory packages are installed to.
+    /// An explicit path wins over a named prefix, which wins over the configured install path.
+    /// A relative explicit path is resolved against the current directory.
+    pub fn resolve_install_path(
+        &self,
+        prefix: Option<&str>,
+        install_path: Option<&Path>,
+    ) -> Result<PathBuf, Error> {
+        if let Some(install_path) = install_path {
+            if install_path.is_absolute() {
+                return Ok(install_path.to_path_buf());
+            }
+            let current_dir =
+                env::current_dir().map_err(|error| Error::Io(install_path.to_path_buf(), error))?;
+            return Ok(current_dir.join(install_path));
+        }
+        match prefix {
+            Some(prefix) => self
+                .prefixes
+                .get(prefix)
+                .cloned()
+                .ok_or_else(|| Error::UnknownPrefix(prefix.to_string())),
+            None => Ok(self.install_path.clone()),
+        }
+    }
+
+    pub fn is_system_mode() -> bool {
+        *SYSTEM_MODE.get().unwrap_or(&false)
+    }
+
+    pub fn create_default_folders() -> Result<(), Error> {
+        if Config::is_system_mode() {
+            // Only root can create these, a database which is not writable is reported by the caller
+            let _ = fs::create_dir_all(Config::get_database_base_path());
+            let cache_path = Config::get_cache_base_path();
+            let _ = fs::create_dir_all(&cache_path);
+            if !Config::is_cache_writable() {
+                eprintln!(
+                    "{} is not writable, downloads and responses are not cached",
+                    cache_path.display()
+                );
+            }
+            return Ok(());
+        }
+        for path in [
+            Config::get_config_base_path(),
+            Config::get_database_base_path(),
+            Config::get_cache_base_path(),
+        ] {
+            fs::create_dir_all(&path).map_err(|error| Error::Io(path, error))?;
+        }
+        Ok(())
+    }
+
+    pub fn get_config_path() -> PathBuf {
         Config::get_config_base_path().join("config.toml")
     }
 
@@ -54,15 +337,227 @@ impl Config {
         Config::get_database_base_path().join("packages.db")
     }
 
+    pub fn get_manifest_path() -> PathBuf {
+        Config::get_config_base_path().join("packages.toml")
+    }
+
+    /// The system-wide cache may only be writable by root, other users skip caching.
+    pub fn is_cache_writable() -> bool {
+        privilege::is_writable(&Config::get_cache_base_path())
+    }
+
+    pub fn get_http_cache_path() -> PathBuf {
+        Config::get_cache_base_path().join("http")
+    }
+
+    pub fn get_download_cache_path() -> PathBuf {
+        Config::get_cache_base_path().join("downloads")
+    }
+
+    /// Install path if none is configured, `$XDG_BIN_HOME` and as fallback *~/.local/bin*.
+    pub fn get_default_install_path() -> PathBuf {
+        if Config::is_system_mode() {
+            return PathBuf::from(SYSTEM_INSTALL_PATH);
+        }
+        user_install_path(std::env::var("XDG_BIN_HOME").ok().as_deref())
+    }
+
     fn get_config_base_path() -> PathBuf {
+        if Config::is_system_mode() {
+            return PathBuf::from(SYSTEM_CONFIG_PATH);
+        }
         let base_path = std::env::var("XDG_CONFIG_HOME").unwrap_or("~/.config".to_string());
         let base_path = shellexpand::tilde(&base_path);
         Path::new(base_path.as_ref()).join("grpm")
     }
 
-    fn get_database_base_path() -> PathBuf {
+    pub fn get_database_base_path() -> PathBuf {
+        if Config::is_system_mode() {
+            return PathBuf::from(SYSTEM_DATABASE_PATH);
+        }
+        Config::get_data_path().join("grpm")
+    }
+
+    /// Directory shell completions and man pages are installed to.
+    pub fn get_data_path() -> PathBuf {
+        if Config::is_system_mode() {
+            return PathBuf::from(SYSTEM_DATA_PATH);
+        }
         let base_path = std::env::var("XDG_DATA_HOME").unwrap_or("~/.local/share".to_string());
         let base_path = shellexpand::tilde(&base_path);
+        PathBuf::from(base_path.as_ref())
+    }
+
+    fn get_cache_base_path() -> PathBuf {
+        if Config::is_system_mode() {
+            return PathBuf::from(SYSTEM_CACHE_PATH);
+        }
+        let base_path = std::env::var("XDG_CACHE_HOME").unwrap_or("~/.cache".to_string());
+        let base_path = shellexpand::tilde(&base_path);
         Path::new(base_path.as_ref()).join("grpm")
     }
 }
+
+/// Sets a key in the document, values of `jobs` are stored as integer.
+fn set_value(document: &mut Document, key: &str, new_value: &str) -> Result<(), Error> {
+    match key {
+        "token" | "install_path" => assign(&mut document[key], Value::from(new_value)),
+        "jobs" => {
+            let jobs = match new_value.parse::<i64>() {
+                Ok(jobs) => Value::from(jobs),
+                // Stored as given, so validation reports it
+                Err(_) => Value::from(new_value),
+            };
+            assign(&mut document[key], jobs);
+        }
+        _ => {
+            let Some(name) = key
+                .strip_prefix("prefixes.")
+                .filter(|name| !name.is_empty())
+            else {
+                return Err(Error::UnknownKey(key.to_string()));
+            };
+            if !document.contains_key("prefixes") {
+                document["prefixes"] = Item::Table(Table::new());
+            }
+            assign(&mut document["prefixes"][name], Value::from(new_value));
+        }
+    }
+    Ok(())
+}
+
+/// Replaces a value, keeping the comments around the old one.
+fn assign(item: &mut Item, mut new_value: Value) {
+    if let Some(old_value) = item.as_value() {
+        *new_value.decor_mut() = old_value.decor().clone();
+    }
+    *item = Item::Value(new_value);
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn document(content: &str) -> Document {
+        content.parse::<Document>().unwrap()
+    }
+
+    fn config() -> Config {
+        Config::from_document(&document(
+            r#"
+            install_path = "/home/user/.local/bin"
+
+            [prefixes]
+            system = "/usr/local/bin"
+            "#,
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn defaults() {
+        let config = Config::from_document(&Document::new()).unwrap();
+        assert_eq!(config.jobs, 4);
+        assert!(config.token.is_none());
+        assert!(config.prefixes.is_empty());
+    }
+
+    #[test]
+    fn default_install_path() {
+        let fallback = expand_path("~/.local/bin");
+        assert!(fallback.is_absolute());
+        assert_eq!(user_install_path(None), fallback);
+        assert_eq!(user_install_path(Some("")), fallback);
+        assert_eq!(user_install_path(Some("bin")), fallback);
+        assert_eq!(
+            user_install_path(Some("/opt/bin")),
+            PathBuf::from("/opt/bin")
+        );
+    }
+
+    #[test]
+    fn field_errors() {
+        let errors = Config::from_document(&document(
+            r#"
+            jobs = 0
+            install_path = 1
+            tokn = "secret"
+
+            [prefixes]
+            work = true
+            "#,
+        ))
+        .err()
+        .unwrap();
+        let keys: Vec<&str> = errors.iter().map(|error| error.key.as_str()).collect();
+        assert_eq!(keys, vec!["jobs", "install_path", "tokn", "prefixes.work"]);
+        assert_eq!(errors[2].message, "unknown key");
+    }
+
+    #[test]
+    fn get_values() {
+        let config = config();
+        assert_eq!(config.get("jobs").unwrap(), Some("4".to_string()));
+        assert_eq!(
+            config.get("prefixes.system").unwrap(),
+            Some("/usr/local/bin".to_string())
+        );
+        assert_eq!(config.get("token").unwrap(), None);
+        assert!(config.get("path").is_err());
+    }
+
+    #[test]
+    fn set_keeps_comments() {
+        let mut document = document("# Installed binaries\ninstall_path = \"~/bin\" # home\n");
+        set_value(&mut document, "jobs", "8").unwrap();
+        set_value(&mut document, "install_path", "/opt/bin").unwrap();
+        set_value(&mut document, "prefixes.work", "/opt/work").unwrap();
+        let content = document.to_string();
+        assert!(content.starts_with("# Installed binaries\n"));
+        assert!(content.contains("install_path = \"/opt/bin\" # home"));
+        assert!(content.contains("jobs = 8"));
+        assert!(content.contains("[prefixes]\nwork = \"/opt/work\""));
+        let config = Config::from_document(&document).unwrap();
+        assert_eq!(config.jobs, 8);
+    }
+
+    #[test]
+    fn set_invalid() {
+        let mut document = Document::new();
+        assert!(set_value(&mut document, "path", "/opt/bin").is_err());
+        assert!(set_value(&mut document, "prefixes.", "/opt/bin").is_err());
+        set_value(&mut document, "jobs", "many").unwrap();
+        assert!(Config::from_document(&document).is_err());
+    }
+
+    #[test]
+    fn resolve_default() {
+        let path = config().resolve_install_path(None, None).unwrap();
+        assert_eq!(path, PathBuf::from("/home/user/.local/bin"));
+    }
+
+    #[test]
+    fn resolve_prefix() {
+        let path = config().resolve_install_path(Some("system"), None).unwrap();
+        assert_eq!(path, PathBuf::from("/usr/local/bin"));
+        assert!(config().resolve_install_path(Some("work"), None).is_err());
+    }
+
+    #[test]
+    fn resolve_install_path() {
+        let path = config()
+            .resolve_install_path(Some("system"), Some(Path::new("/opt/bin")))
+            .unwrap();
+        assert_eq!(path, PathBuf::from("/opt/bin"));
+    }
+
+    #[test]
+    fn resolve_relative_install_path() {
+        let path = config()
+            .resolve_install_path(None, Some(Path::new("bin")))
+            .unwrap();
+        assert!(path.is_absolute());
+        assert_eq!(path, env::current_dir().unwrap().join("bin"));
+    }
+}
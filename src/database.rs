@@ -1,24 +1,172 @@
 use jammdb::DB;
-use serde::{Deserialize, Serialize};
-use std::{error::Error, path::Path};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::{collections::BTreeMap, fmt, fs, io, path::Path};
 
 const BUCKET_NAME: &str = "PACKAGES";
 
 pub struct Database {
-    database: DB,
+    storage: Storage,
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+enum Storage {
+    File(DB),
+    /// Records read for a dry run, keyed like migrated records. Changes are discarded.
+    Snapshot(BTreeMap<String, Package>),
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Storage(jammdb::Error),
+    Encoding(String),
+    NotInstalled(Vec<String>),
+    /// Several packages are installed under the name, only their full names are unique.
+    Ambiguous {
+        name: String,
+        full_names: Vec<String>,
+    },
+    /// The file is already installed by another package.
+    Conflict {
+        path: String,
+        owner: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Storage(error) => write!(f, "{}", error),
+            Error::Encoding(error) => write!(f, "Invalid package record: {}", error),
+            Error::NotInstalled(names) => write!(f, "{} not installed", names.join(", ")),
+            Error::Ambiguous { name, full_names } => write!(
+                f,
+                "{} is installed from {}, use the full name",
+                name,
+                full_names.join(" and ")
+            ),
+            Error::Conflict { path, owner } => write!(
+                f,
+                "{} is already installed by {}, choose another name with --as or --bin-name",
+                path, owner
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<jammdb::Error> for Error {
+    fn from(error: jammdb::Error) -> Self {
+        Error::Storage(error)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct Package {
     pub name: String,
     pub full_name: String,
     pub version: String,
     pub binary: String,
     pub path: String,
+    #[serde(default)]
+    pub asset: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Path of the binary in the release archive.
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub strip_components: usize,
+    /// Further installed files like shell completions and man pages.
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// SHA-256 of the installed binary and further files by their path.
+    #[serde(default)]
+    pub checksums: BTreeMap<String, String>,
+}
+
+/// Returns the key of a package, GitHub treats repository names case-insensitively.
+pub fn key(full_name: &str) -> String {
+    full_name.to_lowercase()
+}
+
+/// Package and binary names become file names in the install path, so they must not contain directories.
+pub fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err("must be a file name without directories".to_string());
+    }
+    Ok(())
+}
+
+/// Deserializes a name which passes `check_name`.
+pub fn deserialize_name<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    check_name(&name)
+        .map_err(|error| de::Error::custom(format!("invalid name '{}', {}", name, error)))?;
+    Ok(name)
+}
+
+/// Deserializes an optional name which passes `check_name`.
+pub fn deserialize_optional_name<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|name| {
+            check_name(&name).map_err(|error| {
+                de::Error::custom(format!("invalid name '{}', {}", name, error))
+            })?;
+            Ok(name)
+        })
+        .transpose()
+}
+
+impl Package {
+    pub fn key(&self) -> String {
+        key(&self.full_name)
+    }
+}
+
+/// Layout of the bincode encoded records written before packages were stored as JSON.
+#[derive(Deserialize)]
+struct LegacyPackage {
+    name: String,
+    full_name: String,
+    version: String,
+    binary: String,
+    path: String,
+}
+
+impl From<LegacyPackage> for Package {
+    fn from(legacy: LegacyPackage) -> Self {
+        Package {
+            name: legacy.name,
+            full_name: legacy.full_name,
+            version: legacy.version,
+            binary: legacy.binary,
+            path: legacy.path,
+            asset: None,
+            url: None,
+            sha256: None,
+            source: None,
+            strip_components: 0,
+            files: Vec::new(),
+            checksums: BTreeMap::new(),
+        }
+    }
+}
+
+fn decode(value: &[u8]) -> Result<Package, Error> {
+    match serde_json::from_slice(value) {
+        Ok(package) => Ok(package),
+        Err(_) => bincode::deserialize::<LegacyPackage>(value)
+            .map(Package::from)
+            .map_err(|error| Error::Encoding(error.to_string())),
+    }
 }
 
 impl Database {
-    pub fn new(path: impl AsRef<Path>) -> Result<Database, jammdb::Error> {
+    pub fn new(path: impl AsRef<Path>) -> Result<Database, Error> {
         let database = DB::open(path)?;
         let tx = database.tx(true)?;
 
@@ -27,41 +175,158 @@ impl Database {
         }
 
         tx.commit()?;
-        Ok(Database { database })
+        let database = Database {
+            storage: Storage::File(database),
+        };
+        database.migrate_keys()?;
+        Ok(database)
+    }
+
+    /// Reads the records without writing anything, not even the key migration or a missing file.
+    pub fn snapshot(path: impl AsRef<Path>) -> Result<Database, Error> {
+        let mut packages = BTreeMap::new();
+        if path.as_ref().exists() {
+            // jammdb always opens the file for writing, a database only root may write is read from a copy
+            let copy;
+            let database = match DB::open(path.as_ref()) {
+                Err(jammdb::Error::IOError(error))
+                    if error.kind() == io::ErrorKind::PermissionDenied =>
+                {
+                    copy = tempfile::NamedTempFile::new().map_err(jammdb::Error::IOError)?;
+                    fs::copy(path.as_ref(), copy.path()).map_err(jammdb::Error::IOError)?;
+                    DB::open(copy.path())?
+                }
+                result => result?,
+            };
+            let tx = database.tx(false)?;
+            if let Ok(bucket) = tx.get_bucket(BUCKET_NAME) {
+                for data in bucket.cursor() {
+                    if let jammdb::Data::KeyValue(kv) = &*data {
+                        let package = decode(kv.value())?;
+                        packages.insert(package.key(), package);
+                    }
+                }
+            }
+        }
+        Ok(Database {
+            storage: Storage::Snapshot(packages),
+        })
+    }
+
+    /// Packages used to be keyed by their repository name only, so `foo/cli` and `bar/cli` overwrote each other.
+    fn migrate_keys(&self) -> Result<(), Error> {
+        let Storage::File(database) = &self.storage else {
+            return Ok(());
+        };
+        let tx = database.tx(true)?;
+        let bucket = tx.get_bucket(BUCKET_NAME)?;
+        let mut migrated = Vec::new();
+        for data in bucket.cursor() {
+            if let jammdb::Data::KeyValue(kv) = &*data {
+                let package = decode(kv.value())?;
+                if kv.key() != package.key().as_bytes() {
+                    migrated.push((kv.key().to_vec(), package));
+                }
+            }
+        }
+        if migrated.is_empty() {
+            return Ok(());
+        }
+        for (old_key, package) in migrated {
+            bucket.delete(old_key)?;
+            let value =
+                serde_json::to_vec(&package).map_err(|error| Error::Encoding(error.to_string()))?;
+            bucket.put(package.key().into_bytes(), value)?;
+        }
+        tx.commit()?;
+        Ok(())
     }
 
-    pub fn put(&self, key: &str, package: &Package) -> Result<(), Box<dyn Error>> {
-        let tx = self.database.tx(true)?;
+    pub fn put(&self, key: &str, package: &Package) -> Result<(), Error> {
+        let Storage::File(database) = &self.storage else {
+            return Ok(());
+        };
+        let tx = database.tx(true)?;
         let bucket = tx.get_bucket(BUCKET_NAME)?;
-        let value = bincode::serialize(&package)?;
+        let value =
+            serde_json::to_vec(&package).map_err(|error| Error::Encoding(error.to_string()))?;
         bucket.put(key.as_bytes(), value)?;
         tx.commit()?;
         Ok(())
     }
 
-    pub fn remove(&self, key: &str) -> Result<(), jammdb::Error> {
-        let tx = self.database.tx(true)?;
+    pub fn remove(&self, key: &str) -> Result<(), Error> {
+        let Storage::File(database) = &self.storage else {
+            return Ok(());
+        };
+        let tx = database.tx(true)?;
         let bucket = tx.get_bucket(BUCKET_NAME)?;
         bucket.delete(key)?;
         tx.commit()?;
         Ok(())
     }
 
-    pub fn get(&self, key: &str) -> Result<Option<Package>, Box<dyn Error>> {
-        let tx = self.database.tx(false)?;
+    pub fn get(&self, key: &str) -> Result<Option<Package>, Error> {
+        let database = match &self.storage {
+            Storage::File(database) => database,
+            Storage::Snapshot(packages) => return Ok(packages.get(key).cloned()),
+        };
+        let tx = database.tx(false)?;
         let bucket = tx.get_bucket(BUCKET_NAME)?;
         if let Some(kv) = bucket.get_kv(key) {
-            return Ok(Some(bincode::deserialize(kv.value())?));
+            return Ok(Some(decode(kv.value())?));
         }
         Ok(None)
     }
 
-    pub fn get_all(&self) -> Result<Vec<Package>, Box<dyn Error>> {
-        let tx = self.database.tx(false)?;
+    /// Looks a package up by its full name or by the name it was installed as.
+    pub fn find(&self, name: &str) -> Result<Option<Package>, Error> {
+        if let Some(package) = self.get(&key(name))? {
+            return Ok(Some(package));
+        }
+        let mut packages: Vec<Package> = self
+            .get_all()?
+            .into_iter()
+            .filter(|package| package.name == name)
+            .collect();
+        if packages.len() > 1 {
+            return Err(Error::Ambiguous {
+                name: name.to_string(),
+                full_names: packages
+                    .iter()
+                    .map(|package| package.full_name.to_string())
+                    .collect(),
+            });
+        }
+        Ok(packages.pop())
+    }
+
+    /// Fails if another package than the one with the given key installed a file at the path.
+    pub fn ensure_unclaimed(&self, path: &Path, key: &str) -> Result<(), Error> {
+        let owner = self.get_all()?.into_iter().find(|package| {
+            package.key() != key
+                && (Path::new(&package.path).join(&package.binary) == path
+                    || package.files.iter().any(|file| Path::new(file) == path))
+        });
+        match owner {
+            Some(owner) => Err(Error::Conflict {
+                path: path.to_string_lossy().to_string(),
+                owner: owner.full_name,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get_all(&self) -> Result<Vec<Package>, Error> {
+        let database = match &self.storage {
+            Storage::File(database) => database,
+            Storage::Snapshot(packages) => return Ok(packages.values().cloned().collect()),
+        };
+        let tx = database.tx(false)?;
         let mut packages: Vec<Package> = Vec::new();
         for data in tx.get_bucket(BUCKET_NAME)?.cursor() {
             if let jammdb::Data::KeyValue(kv) = &*data {
-                let package: Package = bincode::deserialize(kv.value())?;
+                let package = decode(kv.value())?;
                 packages.push(package)
             }
         }
@@ -73,6 +338,7 @@ impl Database {
 mod tests {
 
     use super::*;
+    use crate::test_util;
     use std::{fs, path::Path};
     static PATH: &str = "test.db";
 
@@ -88,7 +354,7 @@ mod tests {
     fn new() {
         let db = setup();
         assert!(Path::new(PATH).exists());
-        let tx = db.database.tx(false).unwrap();
+        let tx = file(&db).tx(false).unwrap();
         let _ = tx.get_bucket(BUCKET_NAME).unwrap();
     }
 
@@ -97,11 +363,9 @@ mod tests {
         let db = setup();
         let key = "Test";
         let package = Package {
-            name: "Test Package".to_string(),
-            full_name: "Test/Package".to_string(),
-            path: "/test/path".to_string(),
-            version: "v1.2.3".to_string(),
-            binary: "test".to_string(),
+            asset: Some("test.tar.gz".to_string()),
+            url: Some("https://example.com/test.tar.gz".to_string()),
+            ..test_util::package("test", Path::new("/test/path"))
         };
         db.put(key, &package).unwrap();
         let result = db.get(key).unwrap();
@@ -116,11 +380,9 @@ mod tests {
         let key2 = "Test2";
 
         let package = Package {
-            name: "Test Package".to_string(),
-            full_name: "Test/Package".to_string(),
-            path: "/test/path".to_string(),
-            version: "v1.2.3".to_string(),
-            binary: "test".to_string(),
+            asset: Some("test.tar.gz".to_string()),
+            url: Some("https://example.com/test.tar.gz".to_string()),
+            ..test_util::package("test", Path::new("/test/path"))
         };
 
         db.put(key1, &package).unwrap();
@@ -136,15 +398,127 @@ mod tests {
         let db = setup();
         let key = "Test";
         let package = Package {
-            name: "Test Package".to_string(),
-            full_name: "Test/Package".to_string(),
-            path: "/test/path".to_string(),
-            version: "v1.2.3".to_string(),
-            binary: "test".to_string(),
+            asset: Some("test.tar.gz".to_string()),
+            url: Some("https://example.com/test.tar.gz".to_string()),
+            ..test_util::package("test", Path::new("/test/path"))
         };
         db.put(key, &package).unwrap();
         db.remove(key).unwrap();
         let result = db.get(key).unwrap();
         assert!(result.is_none());
     }
+
+    #[test]
+    fn get_legacy() {
+        #[derive(Serialize)]
+        struct Legacy<'a> {
+            name: &'a str,
+            full_name: &'a str,
+            version: &'a str,
+            binary: &'a str,
+            path: &'a str,
+        }
+
+        let db = setup();
+        let key = "Legacy";
+        let legacy = Legacy {
+            name: "Test Package",
+            full_name: "Test/Package",
+            version: "v1.2.3",
+            binary: "test",
+            path: "/test/path",
+        };
+        {
+            let tx = file(&db).tx(true).unwrap();
+            let bucket = tx.get_bucket(BUCKET_NAME).unwrap();
+            bucket
+                .put(key.as_bytes(), bincode::serialize(&legacy).unwrap())
+                .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let package = db.get(key).unwrap().unwrap();
+        assert_eq!(package.full_name, "Test/Package");
+        assert_eq!(package.binary, "test");
+        assert!(package.asset.is_none());
+    }
+
+    fn file(db: &Database) -> &DB {
+        match &db.storage {
+            Storage::File(database) => database,
+            Storage::Snapshot(_) => panic!("not backed by a file"),
+        }
+    }
+
+    fn package(name: &str, full_name: &str) -> Package {
+        Package {
+            full_name: full_name.to_string(),
+            ..test_util::package(name, Path::new("/test/path"))
+        }
+    }
+
+    #[test]
+    fn migrate_keys() {
+        let path = Path::new("test-migrate.db");
+        if path.exists() {
+            fs::remove_file(path).unwrap();
+        }
+        {
+            let db = Database::new(path).unwrap();
+            let tx = file(&db).tx(true).unwrap();
+            let bucket = tx.get_bucket(BUCKET_NAME).unwrap();
+            let value = serde_json::to_vec(&package("cli", "Foo/cli")).unwrap();
+            bucket.put("cli", value).unwrap();
+            tx.commit().unwrap();
+        }
+        let snapshot = Database::snapshot(path).unwrap();
+        assert!(snapshot.get("cli").unwrap().is_none());
+        assert_eq!(
+            snapshot.get("foo/cli").unwrap().unwrap().full_name,
+            "Foo/cli"
+        );
+        snapshot.remove("foo/cli").unwrap();
+        drop(snapshot);
+
+        let db = Database::new(path).unwrap();
+        assert!(db.get("cli").unwrap().is_none());
+        assert_eq!(db.get("foo/cli").unwrap().unwrap().full_name, "Foo/cli");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn snapshot_of_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("packages.db");
+        let snapshot = Database::snapshot(&path).unwrap();
+        assert!(snapshot.get_all().unwrap().is_empty());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn find() {
+        let path = Path::new("test-find.db");
+        if path.exists() {
+            fs::remove_file(path).unwrap();
+        }
+        let db = Database::new(path).unwrap();
+        let foo = package("cli", "foo/cli");
+        let bar = package("cli", "bar/cli");
+        let bat = package("batcat", "sharkdp/bat");
+        for package in [&foo, &bar, &bat] {
+            db.put(&package.key(), package).unwrap();
+        }
+        assert_eq!(db.find("Foo/cli").unwrap(), Some(foo));
+        assert_eq!(db.find("batcat").unwrap(), Some(bat));
+        assert!(db.find("bat").unwrap().is_none());
+        assert!(matches!(db.find("cli"), Err(Error::Ambiguous { .. })));
+        assert!(matches!(
+            db.ensure_unclaimed(Path::new("/test/path/cli"), "baz/cli"),
+            Err(Error::Conflict { .. })
+        ));
+        assert!(db
+            .ensure_unclaimed(Path::new("/test/path/batcat"), "sharkdp/bat")
+            .is_ok());
+        fs::remove_file(path).unwrap();
+    }
 }
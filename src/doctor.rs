@@ -0,0 +1,615 @@
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    database::{self, Database, Package},
+    hash, privilege,
+    repo::{self, ApiStatus},
+    search_path, staging,
+};
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Ok,
+    Warning,
+    Problem,
+    Fixed,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Ok => write!(f, "ok"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Problem => write!(f, "problem"),
+            Severity::Fixed => write!(f, "fixed"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Database(database::Error),
+    Io(io::Error),
+    Privilege(privilege::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Database(error) => write!(f, "{}", error),
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Privilege(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<database::Error> for Error {
+    fn from(error: database::Error) -> Self {
+        Error::Database(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<privilege::Error> for Error {
+    fn from(error: privilege::Error) -> Self {
+        Error::Privilege(error)
+    }
+}
+
+/// Drift between the database and the installed files which `--fix` repairs.
+#[derive(PartialEq, Debug)]
+pub enum Repair {
+    /// Removes the record of a package whose binary is gone, by its key.
+    RemovePackage(String),
+    /// Removes missing files from the record of a package, by its key.
+    ForgetFiles(String, Vec<String>),
+    SetExecutable(PathBuf),
+    RemoveLeftover(PathBuf),
+}
+
+/// Result of a single check.
+#[derive(Serialize, Debug)]
+pub struct Finding {
+    pub check: String,
+    pub severity: Severity,
+    pub message: String,
+    pub suggestion: Option<String>,
+    #[serde(skip)]
+    pub repair: Option<Repair>,
+}
+
+impl Finding {
+    pub fn ok(check: &str, message: String) -> Finding {
+        Finding {
+            check: check.to_string(),
+            severity: Severity::Ok,
+            message,
+            suggestion: None,
+            repair: None,
+        }
+    }
+
+    pub fn warning(check: &str, message: String, suggestion: &str) -> Finding {
+        Finding {
+            severity: Severity::Warning,
+            suggestion: Some(suggestion.to_string()),
+            ..Finding::ok(check, message)
+        }
+    }
+
+    pub fn problem(check: &str, message: String, suggestion: &str) -> Finding {
+        Finding {
+            severity: Severity::Problem,
+            suggestion: Some(suggestion.to_string()),
+            ..Finding::ok(check, message)
+        }
+    }
+
+    fn with_repair(self, repair: Repair) -> Finding {
+        Finding {
+            repair: Some(repair),
+            ..self
+        }
+    }
+}
+
+/// Reports the token and the remaining rate limit.
+pub fn check_token(has_token: bool, status: Result<ApiStatus, repo::Error>) -> Finding {
+    let status = match status {
+        Ok(status) => status,
+        Err(repo::Error::Unauthorized) => {
+            return Finding::problem(
+                "token",
+                "The configured token was rejected by GitHub".to_string(),
+                "Create a new token and run `grpm config set token <TOKEN>`",
+            )
+        }
+        Err(error) => {
+            return Finding::warning(
+                "token",
+                format!("Could not reach GitHub: {}", error),
+                "Check the network connection",
+            )
+        }
+    };
+
+    let rate_limit = format!(
+        "{} of {} requests left, resets in {} minute(s)",
+        status.remaining,
+        status.limit,
+        status.reset_in.as_secs() / 60 + 1
+    );
+    if status.remaining == 0 {
+        return Finding::warning(
+            "token",
+            format!("Rate limit exceeded, {}", rate_limit),
+            "Wait for the reset or configure a token",
+        );
+    }
+    if !has_token {
+        return Finding::warning(
+            "token",
+            format!("No token configured, {}", rate_limit),
+            "Run `grpm config set token <TOKEN>` to raise the rate limit",
+        );
+    }
+    let scopes = match status.scopes {
+        Some(scopes) if scopes.is_empty() => "no scopes".to_string(),
+        Some(scopes) => format!("scopes {}", scopes.join(", ")),
+        None => "fine-grained".to_string(),
+    };
+    Finding::ok(
+        "token",
+        format!("Token accepted ({}), {}", scopes, rate_limit),
+    )
+}
+
+/// Checks that packages can be installed to the directory and run from it.
+pub fn check_install_path(install_path: &Path, directories: &[PathBuf]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let display = install_path.display();
+    if !install_path.is_dir() {
+        findings.push(Finding::warning(
+            "install path",
+            format!("{} does not exist", display),
+            "It is created by the next installation",
+        ));
+    }
+    if privilege::ensure_placeable(install_path).is_err() {
+        findings.push(Finding::problem(
+            "install path",
+            format!(
+                "{} is not writable and neither sudo nor doas is available",
+                display
+            ),
+            "Run `grpm config set install_path <PATH>` with a writable directory",
+        ));
+    }
+    if !search_path::contains(directories, install_path) {
+        findings.push(Finding::warning(
+            "install path",
+            format!("{} is not in $PATH", display),
+            "Add it to $PATH in your shell profile",
+        ));
+    }
+    if findings.is_empty() {
+        findings.push(Finding::ok(
+            "install path",
+            format!("{} is writable and in $PATH", display),
+        ));
+    }
+    findings
+}
+
+/// Checks that the binary and further files of a package are installed as recorded.
+pub fn check_package(package: &Package, directories: &[PathBuf]) -> Vec<Finding> {
+    let check = format!("package {}", package.name);
+    let mut findings = Vec::new();
+    let binary_path = Path::new(&package.path).join(&package.binary);
+    let reinstall = format!(
+        "Reinstall with `grpm install {} --tag {}`",
+        package.full_name, package.version
+    );
+
+    if !binary_path.is_file() {
+        findings.push(
+            Finding::problem(
+                &check,
+                format!("{} is missing", binary_path.display()),
+                &format!("{} or run `grpm doctor --fix` to forget it", reinstall),
+            )
+            .with_repair(Repair::RemovePackage(package.key())),
+        );
+        return findings;
+    }
+    if !search_path::is_executable(&binary_path) {
+        findings.push(
+            Finding::problem(
+                &check,
+                format!("{} is not executable", binary_path.display()),
+                "Run `grpm doctor --fix`",
+            )
+            .with_repair(Repair::SetExecutable(binary_path.clone())),
+        );
+    }
+    if let Some(shadowing) =
+        search_path::find_shadowing(directories, Path::new(&package.path), &package.binary)
+    {
+        findings.push(Finding::warning(
+            &check,
+            format!(
+                "{} is shadowed by {}",
+                binary_path.display(),
+                shadowing.display()
+            ),
+            "Remove the other binary or reorder $PATH",
+        ));
+    }
+
+    let missing_files: Vec<String> = package
+        .files
+        .iter()
+        .filter(|file| !Path::new(file).exists())
+        .cloned()
+        .collect();
+    if !missing_files.is_empty() {
+        findings.push(
+            Finding::warning(
+                &check,
+                format!("{} is missing", missing_files.join(", ")),
+                &format!("{} or run `grpm doctor --fix` to forget them", reinstall),
+            )
+            .with_repair(Repair::ForgetFiles(package.key(), missing_files.clone())),
+        );
+    }
+
+    for (path, checksum) in &package.checksums {
+        if missing_files.contains(path) {
+            continue;
+        }
+        match hash::sha256_file(Path::new(path)) {
+            Ok(actual) if actual == *checksum => {}
+            Ok(_) => findings.push(Finding::problem(
+                &check,
+                format!("{} was modified after the installation", path),
+                &reinstall,
+            )),
+            Err(error) => findings.push(Finding::problem(
+                &check,
+                format!("{} could not be read: {}", path, error),
+                "Check the permissions of the file",
+            )),
+        }
+    }
+
+    if findings.is_empty() {
+        findings.push(Finding::ok(
+            &check,
+            format!("{} {} is intact", package.full_name, package.version),
+        ));
+    }
+    findings
+}
+
+/// Finds files which are recorded for more than one package, so one overwrote the other.
+pub fn check_collisions(packages: &[Package]) -> Vec<Finding> {
+    let mut owners: BTreeMap<PathBuf, Vec<&str>> = BTreeMap::new();
+    for package in packages {
+        let binary_path = Path::new(&package.path).join(&package.binary);
+        for path in std::iter::once(binary_path).chain(package.files.iter().map(PathBuf::from)) {
+            owners.entry(path).or_default().push(&package.name);
+        }
+    }
+    owners
+        .into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .map(|(path, names)| {
+            Finding::problem(
+                "collision",
+                format!("{} is installed by {}", path.display(), names.join(" and ")),
+                "Uninstall all but one of them and reinstall it",
+            )
+        })
+        .collect()
+}
+
+/// Finds untracked files which clash with recorded ones: files in the install directories named
+/// like the binary of a package installed elsewhere, and files for the same command next to an extra.
+pub fn check_untracked(
+    packages: &[Package],
+    install_paths: &BTreeSet<PathBuf>,
+    directories: &[PathBuf],
+) -> Vec<Finding> {
+    let tracked: BTreeSet<PathBuf> = packages
+        .iter()
+        .flat_map(|package| {
+            std::iter::once(Path::new(&package.path).join(&package.binary))
+                .chain(package.files.iter().map(PathBuf::from))
+        })
+        .collect();
+    let mut findings = Vec::new();
+    for package in packages {
+        let binary_path = Path::new(&package.path).join(&package.binary);
+        // Reported as shadowing by the package check
+        let shadowing =
+            search_path::find_shadowing(directories, Path::new(&package.path), &package.binary);
+        for install_path in install_paths {
+            let path = install_path.join(&package.binary);
+            if tracked.contains(&path) || !path.is_file() || shadowing.as_ref() == Some(&path) {
+                continue;
+            }
+            findings.push(Finding::warning(
+                "untracked",
+                format!(
+                    "{} is not tracked and clashes with {}",
+                    path.display(),
+                    binary_path.display()
+                ),
+                &format!("Remove it if it is an old copy of {}", package.name),
+            ));
+        }
+        for file in package.files.iter().map(Path::new) {
+            let (Some(directory), Some(command)) = (file.parent(), command_name(file)) else {
+                continue;
+            };
+            let Ok(entries) = fs::read_dir(directory) else {
+                continue;
+            };
+            let mut clashing: Vec<PathBuf> = entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| !tracked.contains(path) && command_name(path) == Some(command))
+                .collect();
+            clashing.sort();
+            for path in clashing {
+                findings.push(Finding::warning(
+                    "untracked",
+                    format!(
+                        "{} is not tracked and clashes with {}",
+                        path.display(),
+                        file.display()
+                    ),
+                    "Remove it, so the installed file is used",
+                ));
+            }
+        }
+    }
+    findings
+}
+
+/// Command an extra belongs to, e.g. `rg` for *_rg*, *rg.fish* and *rg.1.gz*.
+fn command_name(path: &Path) -> Option<&str> {
+    let name = path.file_name()?.to_str()?.trim_start_matches('_');
+    name.split('.').next().filter(|command| !command.is_empty())
+}
+
+/// Finds files an interrupted installation left in the directories.
+pub fn check_leftovers(directories: &BTreeSet<PathBuf>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for directory in directories {
+        let Ok(entries) = fs::read_dir(directory) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if staging::is_leftover(&entry.file_name().to_string_lossy()) {
+                findings.push(
+                    Finding::warning(
+                        "leftover",
+                        format!(
+                            "{} was left by an interrupted installation",
+                            entry.path().display()
+                        ),
+                        "Run `grpm doctor --fix` to remove it",
+                    )
+                    .with_repair(Repair::RemoveLeftover(entry.path())),
+                );
+            }
+        }
+    }
+    findings
+}
+
+/// Applies the repair of a finding.
+pub fn repair(database: &Database, repair: &Repair) -> Result<(), Error> {
+    match repair {
+        Repair::RemovePackage(key) => database.remove(key)?,
+        Repair::ForgetFiles(key, files) => {
+            if let Some(mut package) = database.get(key)? {
+                package.files.retain(|file| !files.contains(file));
+                package.checksums.retain(|path, _| !files.contains(path));
+                database.put(key, &package)?;
+            }
+        }
+        Repair::SetExecutable(path) => privilege::set_executable(path)?,
+        Repair::RemoveLeftover(path) if path.is_dir() => fs::remove_dir_all(path)?,
+        Repair::RemoveLeftover(path) => privilege::remove_file(path)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::{self, executable};
+    use std::os::unix::fs::PermissionsExt;
+
+    fn install(dir: &Path, name: &str, content: &str) -> Package {
+        let path = dir.join(name);
+        executable(&path, content);
+        let mut package = test_util::package(name, dir);
+        package.checksums.insert(
+            path.to_string_lossy().to_string(),
+            hash::sha256_file(&path).unwrap(),
+        );
+        package
+    }
+
+    fn severities(findings: &[Finding]) -> Vec<Severity> {
+        findings.iter().map(|finding| finding.severity).collect()
+    }
+
+    #[test]
+    fn intact_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let package = install(dir.path(), "rg", "binary");
+        let findings = check_package(&package, &[dir.path().to_path_buf()]);
+        assert_eq!(severities(&findings), vec![Severity::Ok]);
+    }
+
+    #[test]
+    fn missing_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let findings = check_package(&test_util::package("rg", dir.path()), &[]);
+        assert_eq!(severities(&findings), vec![Severity::Problem]);
+        assert_eq!(
+            findings[0].repair,
+            Some(Repair::RemovePackage("owner/rg".to_string()))
+        );
+    }
+
+    #[test]
+    fn modified_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let package = install(dir.path(), "rg", "binary");
+        fs::write(dir.path().join("rg"), "changed").unwrap();
+        fs::set_permissions(dir.path().join("rg"), fs::Permissions::from_mode(0o644)).unwrap();
+        let findings = check_package(&package, &[dir.path().to_path_buf()]);
+        assert_eq!(
+            severities(&findings),
+            vec![Severity::Problem, Severity::Problem]
+        );
+        assert_eq!(
+            findings[0].repair,
+            Some(Repair::SetExecutable(dir.path().join("rg")))
+        );
+        assert!(findings[1].message.contains("modified"));
+    }
+
+    #[test]
+    fn missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut package = install(dir.path(), "rg", "binary");
+        let completion = dir.path().join("_rg").to_string_lossy().to_string();
+        package.files.push(completion.to_string());
+        package
+            .checksums
+            .insert(completion.to_string(), "0".repeat(64));
+        let findings = check_package(&package, &[dir.path().to_path_buf()]);
+        assert_eq!(severities(&findings), vec![Severity::Warning]);
+        assert_eq!(
+            findings[0].repair,
+            Some(Repair::ForgetFiles(
+                "owner/rg".to_string(),
+                vec![completion]
+            ))
+        );
+    }
+
+    #[test]
+    fn collisions() {
+        let dir = Path::new("/bin");
+        let mut fd = test_util::package("fd", dir);
+        fd.binary = "rg".to_string();
+        let findings = check_collisions(&[
+            test_util::package("rg", dir),
+            fd,
+            test_util::package("bat", dir),
+        ]);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].message, "/bin/rg is installed by rg and fd");
+    }
+
+    #[test]
+    fn untracked() {
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("bin");
+        let other = dir.path().join("other");
+        let man = dir.path().join("man1");
+        for directory in [&bin, &other, &man] {
+            fs::create_dir(directory).unwrap();
+        }
+        let mut rg = install(&bin, "rg", "binary");
+        rg.files
+            .push(man.join("rg.1").to_string_lossy().to_string());
+        fs::write(man.join("rg.1"), "").unwrap();
+        fs::write(man.join("rg.1.gz"), "").unwrap();
+        fs::write(man.join("rga.1"), "").unwrap();
+        executable(&other.join("rg"), "old");
+        executable(&other.join("fd"), "untracked");
+
+        let install_paths = BTreeSet::from([bin.clone(), other.clone()]);
+        let findings = check_untracked(&[rg], &install_paths, std::slice::from_ref(&bin));
+        let messages: Vec<&str> = findings
+            .iter()
+            .map(|finding| finding.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                format!(
+                    "{} is not tracked and clashes with {}",
+                    other.join("rg").display(),
+                    bin.join("rg").display()
+                ),
+                format!(
+                    "{} is not tracked and clashes with {}",
+                    man.join("rg.1.gz").display(),
+                    man.join("rg.1").display()
+                ),
+            ]
+        );
+        // Comes first in $PATH, so it is reported as shadowing instead
+        assert!(check_untracked(
+            &[install(&bin, "rg", "binary")],
+            &install_paths,
+            &[other.clone(), bin.clone()]
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".grpm-staging-abc")).unwrap();
+        fs::write(dir.path().join("rg"), "").unwrap();
+        let findings = check_leftovers(&BTreeSet::from([dir.path().to_path_buf()]));
+        assert_eq!(findings.len(), 1);
+
+        let database_path = dir.path().join("packages.db");
+        let database = Database::new(&database_path).unwrap();
+        repair(&database, findings[0].repair.as_ref().unwrap()).unwrap();
+        assert!(!dir.path().join(".grpm-staging-abc").exists());
+    }
+
+    #[test]
+    fn token() {
+        let status = || ApiStatus {
+            scopes: Some(vec!["repo".to_string()]),
+            limit: 5000,
+            remaining: 4000,
+            reset_in: std::time::Duration::from_secs(120),
+        };
+        let finding = check_token(true, Ok(status()));
+        assert_eq!(finding.severity, Severity::Ok);
+        assert!(finding.message.contains("scopes repo"));
+        assert_eq!(check_token(false, Ok(status())).severity, Severity::Warning);
+        assert_eq!(
+            check_token(true, Err(repo::Error::Unauthorized)).severity,
+            Severity::Problem
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use std::{fmt, io};
+
+use crate::{
+    adopt, archive, config, database, doctor, hash, lockfile, manifest, print, privilege, prompt,
+    repo, selection,
+};
+
+/// Exit codes of the categories below, documented in the README.
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_NETWORK: i32 = 3;
+pub const EXIT_NOT_FOUND: i32 = 4;
+pub const EXIT_UNAUTHORIZED: i32 = 5;
+pub const EXIT_RATE_LIMITED: i32 = 6;
+pub const EXIT_ARCHIVE: i32 = 7;
+pub const EXIT_DATABASE: i32 = 8;
+pub const EXIT_CONFIG: i32 = 9;
+pub const EXIT_PERMISSION: i32 = 10;
+pub const EXIT_INTEGRITY: i32 = 11;
+pub const EXIT_CANCELLED: i32 = 130;
+
+/// Every error a command can end with.
+#[derive(Debug)]
+pub enum Error {
+    Repo(repo::Error),
+    Selection(selection::Error),
+    Archive(archive::Error),
+    Database(database::Error),
+    Config(config::Error),
+    Lockfile(lockfile::Error),
+    Manifest(manifest::Error),
+    Privilege(privilege::Error),
+    Integrity(hash::DigestMismatch),
+    Adopt(adopt::Error),
+    Print(print::Error),
+    /// A global option was passed to a command which does not support it.
+    Unsupported {
+        option: &'static str,
+        command: String,
+    },
+    Cancelled,
+    /// The command went on after failures for these packages, which were reported already.
+    Incomplete(Vec<String>),
+    Io(io::Error),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Repo(error) => match error {
+                repo::Error::Network(_)
+                | repo::Error::Status(..)
+                | repo::Error::Json(_)
+                | repo::Error::NotCached(_) => EXIT_NETWORK,
+                repo::Error::NotFound(_) => EXIT_NOT_FOUND,
+                repo::Error::Unauthorized => EXIT_UNAUTHORIZED,
+                repo::Error::Forbidden | repo::Error::RateLimited { .. } => EXIT_RATE_LIMITED,
+                repo::Error::Io(_) => EXIT_FAILURE,
+            },
+            Error::Archive(_) => EXIT_ARCHIVE,
+            Error::Database(error) => match error {
+                database::Error::NotInstalled(_) => EXIT_NOT_FOUND,
+                database::Error::Ambiguous { .. } | database::Error::Conflict { .. } => {
+                    EXIT_FAILURE
+                }
+                database::Error::Storage(_) | database::Error::Encoding(_) => EXIT_DATABASE,
+            },
+            Error::Config(_)
+            | Error::Lockfile(lockfile::Error::Parse(..))
+            | Error::Manifest(manifest::Error::Parse(..)) => EXIT_CONFIG,
+            Error::Privilege(_) => EXIT_PERMISSION,
+            Error::Integrity(_) => EXIT_INTEGRITY,
+            Error::Adopt(adopt::Error::BinaryNotFound(_)) => EXIT_NOT_FOUND,
+            Error::Unsupported { .. } => EXIT_USAGE,
+            Error::Cancelled => EXIT_CANCELLED,
+            Error::Selection(_)
+            | Error::Lockfile(_)
+            | Error::Manifest(_)
+            | Error::Adopt(_)
+            | Error::Print(_)
+            | Error::Incomplete(_)
+            | Error::Io(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Repo(error) => write!(f, "GitHub Error: {}", error),
+            Error::Selection(error) => write!(f, "Selection Error: {}", error),
+            Error::Archive(error) => write!(f, "Archive Error: {}", error),
+            Error::Database(error) => write!(f, "Database Error: {}", error),
+            Error::Config(error) => write!(f, "Config Error: {}", error),
+            Error::Lockfile(error) => write!(f, "Lockfile Error: {}", error),
+            Error::Manifest(error) => write!(f, "Manifest Error: {}", error),
+            Error::Privilege(error) => write!(f, "Permission Error: {}", error),
+            Error::Integrity(error) => write!(f, "Integrity Error: {}", error),
+            Error::Adopt(error) => write!(f, "Adopt Error: {}", error),
+            Error::Print(error) => write!(f, "Output Error: {}", error),
+            Error::Unsupported { option, command } => {
+                write!(f, "{} is not supported by {}", option, command)
+            }
+            Error::Cancelled => write!(f, "Cancelled by user"),
+            Error::Incomplete(full_names) => write!(f, "Failed for {}", full_names.join(", ")),
+            Error::Io(error) => write!(f, "I/O Error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<repo::Error> for Error {
+    fn from(error: repo::Error) -> Self {
+        Error::Repo(error)
+    }
+}
+
+impl From<selection::Error> for Error {
+    fn from(error: selection::Error) -> Self {
+        match error {
+            selection::Error::Prompt(prompt::Error::Cancelled) => Error::Cancelled,
+            error => Error::Selection(error),
+        }
+    }
+}
+
+impl From<prompt::Error> for Error {
+    fn from(error: prompt::Error) -> Self {
+        match error {
+            prompt::Error::Cancelled => Error::Cancelled,
+            prompt::Error::Io(error) => Error::Io(error),
+        }
+    }
+}
+
+impl From<archive::Error> for Error {
+    fn from(error: archive::Error) -> Self {
+        Error::Archive(error)
+    }
+}
+
+impl From<database::Error> for Error {
+    fn from(error: database::Error) -> Self {
+        Error::Database(error)
+    }
+}
+
+impl From<config::Error> for Error {
+    fn from(error: config::Error) -> Self {
+        Error::Config(error)
+    }
+}
+
+impl From<lockfile::Error> for Error {
+    fn from(error: lockfile::Error) -> Self {
+        Error::Lockfile(error)
+    }
+}
+
+impl From<manifest::Error> for Error {
+    fn from(error: manifest::Error) -> Self {
+        Error::Manifest(error)
+    }
+}
+
+impl From<privilege::Error> for Error {
+    fn from(error: privilege::Error) -> Self {
+        match error {
+            privilege::Error::Io(error) => Error::Io(error),
+            error => Error::Privilege(error),
+        }
+    }
+}
+
+impl From<hash::DigestMismatch> for Error {
+    fn from(error: hash::DigestMismatch) -> Self {
+        Error::Integrity(error)
+    }
+}
+
+impl From<hash::Error> for Error {
+    fn from(error: hash::Error) -> Self {
+        match error {
+            hash::Error::Io(error) => Error::Io(error),
+            hash::Error::Mismatch(mismatch) => Error::Integrity(mismatch),
+        }
+    }
+}
+
+impl From<doctor::Error> for Error {
+    fn from(error: doctor::Error) -> Self {
+        match error {
+            doctor::Error::Database(error) => Error::Database(error),
+            doctor::Error::Io(error) => Error::Io(error),
+            doctor::Error::Privilege(error) => Error::from(error),
+        }
+    }
+}
+
+impl From<adopt::Error> for Error {
+    fn from(error: adopt::Error) -> Self {
+        Error::Adopt(error)
+    }
+}
+
+impl From<print::Error> for Error {
+    fn from(error: print::Error) -> Self {
+        match error {
+            print::Error::Io(error) => Error::Io(error),
+            error => Error::Print(error),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(
+            Error::from(repo::Error::NotFound("Repository a/b".to_string())).exit_code(),
+            EXIT_NOT_FOUND
+        );
+        assert_eq!(
+            Error::from(repo::Error::Unauthorized).exit_code(),
+            EXIT_UNAUTHORIZED
+        );
+        assert_eq!(
+            Error::from(repo::Error::Forbidden).exit_code(),
+            EXIT_RATE_LIMITED
+        );
+        assert_eq!(
+            Error::from(archive::Error::NoBinary).exit_code(),
+            EXIT_ARCHIVE
+        );
+    }
+
+    #[test]
+    fn cancelled() {
+        let error = Error::from(selection::Error::Prompt(prompt::Error::Cancelled));
+        assert_eq!(error.exit_code(), EXIT_CANCELLED);
+    }
+
+    #[test]
+    fn typed_module_errors() {
+        let error = privilege::Error::NotWritable(std::path::PathBuf::from("/usr/local/bin"));
+        assert_eq!(Error::from(error).exit_code(), EXIT_PERMISSION);
+
+        let error = privilege::Error::Io(io::Error::new(io::ErrorKind::StorageFull, "disk full"));
+        assert!(matches!(Error::from(error), Error::Io(_)));
+
+        let error = hash::Error::Io(io::Error::new(io::ErrorKind::NotFound, "missing"));
+        assert!(matches!(Error::from(error), Error::Io(_)));
+
+        let parse_error = toml::from_str::<lockfile::Lockfile>("package = 1").unwrap_err();
+        let error = lockfile::Error::Parse(std::path::PathBuf::from("grpm.lock"), parse_error);
+        assert_eq!(Error::from(error).exit_code(), EXIT_CONFIG);
+
+        let error = manifest::Error::Io(
+            std::path::PathBuf::from("grpm.toml"),
+            io::Error::new(io::ErrorKind::PermissionDenied, "denied"),
+        );
+        assert_eq!(Error::from(error).exit_code(), EXIT_FAILURE);
+
+        let error = Error::Unsupported {
+            option: "--offline",
+            command: "adopt".to_string(),
+        };
+        assert_eq!(error.exit_code(), EXIT_USAGE);
+        assert_eq!(error.to_string(), "--offline is not supported by adopt");
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    ffi::OsStr,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::archive::UnpackedFile;
+
+/// Kind of file shipped next to the binary which is installed as well.
+#[derive(PartialEq, Debug)]
+pub enum Extra {
+    BashCompletion,
+    ZshCompletion,
+    FishCompletion,
+    ManPage(char),
+}
+
+impl Extra {
+    /// Detects completions by their extension or their location in a completion directory,
+    /// man pages by their section suffix like `.1` or `.1.gz`.
+    pub fn detect(path: &Path) -> Option<Extra> {
+        let name = path.file_name()?.to_str()?;
+        if let Some(section) = man_section(name) {
+            return Some(Extra::ManPage(section));
+        }
+
+        let in_completion_dir = path.parent().is_some_and(|parent| {
+            parent.components().any(|component| match component {
+                Component::Normal(dir) => dir.to_string_lossy().to_lowercase().contains("complet"),
+                _ => false,
+            })
+        });
+        let extension = path.extension().and_then(OsStr::to_str);
+        match extension {
+            Some("fish") => Some(Extra::FishCompletion),
+            Some("zsh") => Some(Extra::ZshCompletion),
+            Some("bash") => Some(Extra::BashCompletion),
+            None if in_completion_dir && name.starts_with('_') => Some(Extra::ZshCompletion),
+            _ if in_completion_dir && name.contains("bash") => Some(Extra::BashCompletion),
+            _ => None,
+        }
+    }
+
+    /// Returns where the file is installed below the data directory, e.g. *~/.local/share*.
+    /// Completions are named after the command so shells load them on demand.
+    pub fn destination(&self, name: &str, data_path: &Path) -> PathBuf {
+        let command = name
+            .trim_start_matches('_')
+            .split('.')
+            .next()
+            .unwrap_or(name);
+        match self {
+            Extra::BashCompletion => data_path
+                .join("bash-completion")
+                .join("completions")
+                .join(command),
+            Extra::ZshCompletion => data_path
+                .join("zsh")
+                .join("site-functions")
+                .join(format!("_{}", command)),
+            Extra::FishCompletion => data_path
+                .join("fish")
+                .join("vendor_completions.d")
+                .join(format!("{}.fish", command)),
+            Extra::ManPage(section) => data_path
+                .join("man")
+                .join(format!("man{}", section))
+                .join(name),
+        }
+    }
+}
+
+fn man_section(name: &str) -> Option<char> {
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    let (stem, section) = name.rsplit_once('.')?;
+    let mut chars = section.chars();
+    match (chars.next(), chars.next()) {
+        // Rules out version suffixes like tool-v1.2
+        (Some(section @ '1'..='9'), None)
+            if !stem.is_empty() && !stem.ends_with(|c: char| c.is_ascii_digit()) =>
+        {
+            Some(section)
+        }
+        _ => None,
+    }
+}
+
+/// Finds the completions and man pages among the unpacked files, except the binary,
+/// and returns them with their destination. Files with the same destination are only installed once.
+pub fn find<'a>(
+    files: &'a [UnpackedFile],
+    binary: &UnpackedFile,
+    data_path: &Path,
+) -> Vec<(&'a UnpackedFile, PathBuf)> {
+    let mut extras: Vec<(&UnpackedFile, PathBuf)> = Vec::new();
+    for file in files.iter().filter(|file| file.path != binary.path) {
+        let Some(name) = file.path.file_name().and_then(OsStr::to_str) else {
+            continue;
+        };
+        let Some(extra) = Extra::detect(&file.path) else {
+            continue;
+        };
+        let destination = extra.destination(name, data_path);
+        if !extras.iter().any(|(_, existing)| *existing == destination) {
+            extras.push((file, destination));
+        }
+    }
+    extras
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn unpacked(path: &str) -> UnpackedFile {
+        UnpackedFile {
+            path: PathBuf::from(path),
+            source: path.to_string(),
+            executable: false,
+        }
+    }
+
+    #[test]
+    fn detect_completions() {
+        assert_eq!(
+            Extra::detect(Path::new("complete/rg.bash")),
+            Some(Extra::BashCompletion)
+        );
+        assert_eq!(
+            Extra::detect(Path::new("complete/_rg")),
+            Some(Extra::ZshCompletion)
+        );
+        assert_eq!(
+            Extra::detect(Path::new("autocomplete/bat.zsh")),
+            Some(Extra::ZshCompletion)
+        );
+        assert_eq!(
+            Extra::detect(Path::new("autocomplete/fd.fish")),
+            Some(Extra::FishCompletion)
+        );
+        assert_eq!(Extra::detect(Path::new("complete/_rg.ps1")), None);
+        assert_eq!(Extra::detect(Path::new("_internal")), None);
+    }
+
+    #[test]
+    fn detect_man_pages() {
+        assert_eq!(
+            Extra::detect(Path::new("doc/rg.1")),
+            Some(Extra::ManPage('1'))
+        );
+        assert_eq!(
+            Extra::detect(Path::new("bat.5.gz")),
+            Some(Extra::ManPage('5'))
+        );
+        assert_eq!(Extra::detect(Path::new("tool-v1.10")), None);
+        assert_eq!(Extra::detect(Path::new("tool-v1.2")), None);
+        assert_eq!(Extra::detect(Path::new("README.md")), None);
+    }
+
+    #[test]
+    fn destinations() {
+        let data_path = Path::new("/home/user/.local/share");
+        assert_eq!(
+            Extra::BashCompletion.destination("rg.bash", data_path),
+            PathBuf::from("/home/user/.local/share/bash-completion/completions/rg")
+        );
+        assert_eq!(
+            Extra::ZshCompletion.destination("bat.zsh", data_path),
+            PathBuf::from("/home/user/.local/share/zsh/site-functions/_bat")
+        );
+        assert_eq!(
+            Extra::FishCompletion.destination("fd.fish", data_path),
+            PathBuf::from("/home/user/.local/share/fish/vendor_completions.d/fd.fish")
+        );
+        assert_eq!(
+            Extra::ManPage('1').destination("rg.1", data_path),
+            PathBuf::from("/home/user/.local/share/man/man1/rg.1")
+        );
+    }
+
+    #[test]
+    fn find_extras() {
+        let files = vec![
+            unpacked("rg"),
+            unpacked("complete/_rg"),
+            unpacked("complete/rg.bash"),
+            unpacked("doc/rg.1"),
+            unpacked("doc/CHANGELOG.md"),
+            unpacked("contrib/_rg"),
+            unpacked("complete/rg.zsh"),
+        ];
+        let extras = find(&files, &files[0], Path::new("/share"));
+        let sources: Vec<&str> = extras
+            .iter()
+            .map(|(file, _)| file.source.as_str())
+            .collect();
+        assert_eq!(
+            sources,
+            vec!["complete/_rg", "complete/rg.bash", "doc/rg.1"]
+        );
+    }
+}
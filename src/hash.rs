@@ -0,0 +1,89 @@
+use sha2::{Digest, Sha256};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+#[derive(Debug)]
+pub struct DigestMismatch {
+    pub name: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for DigestMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SHA-256 of {} is {} but {} was expected",
+            self.name, self.actual, self.expected
+        )
+    }
+}
+
+impl std::error::Error for DigestMismatch {}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Mismatch(DigestMismatch),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Mismatch(mismatch) => write!(f, "{}", mismatch),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Returns the hex encoded SHA-256 digest of the file.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Fails if the SHA-256 digest of the file differs from `expected`.
+pub fn verify_sha256(path: &Path, name: &str, expected: &str) -> Result<(), Error> {
+    let actual = sha256_file(path).map_err(Error::Io)?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(Error::Mismatch(DigestMismatch {
+            name: name.to_string(),
+            expected: expected.to_string(),
+            actual,
+        }));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::fs;
+
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn sha256() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hello").unwrap();
+        assert_eq!(sha256_file(file.path()).unwrap(), HELLO_SHA256);
+    }
+
+    #[test]
+    fn verify() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hello").unwrap();
+        assert!(verify_sha256(file.path(), "hello", HELLO_SHA256).is_ok());
+        let error = verify_sha256(file.path(), "hello", "00").unwrap_err();
+        assert!(matches!(error, Error::Mismatch(_)));
+    }
+}
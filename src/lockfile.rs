@@ -0,0 +1,276 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{self, Config},
+    database::{self, Package},
+    repo::RepoInfo,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Serialize(toml::ser::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            Error::Parse(path, error) => write!(f, "{}: {}", path.display(), error),
+            Error::Serialize(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Snapshot of the installed packages, pinning the exact release assets.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Lockfile {
+    #[serde(default, rename = "package")]
+    pub packages: Vec<LockedPackage>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct LockedPackage {
+    #[serde(deserialize_with = "database::deserialize_name")]
+    pub name: String,
+    pub repo: String,
+    pub tag: String,
+    pub asset: String,
+    pub url: String,
+    pub sha256: String,
+    #[serde(deserialize_with = "database::deserialize_name")]
+    pub binary: String,
+    /// Configured prefix the package is installed to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// Directory the package is installed to if it is neither the install path nor a prefix,
+    /// relative to `~` where possible. Other machines resolve it with their own config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub strip_components: usize,
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+impl Lockfile {
+    pub fn load(path: &Path) -> Result<Lockfile, Error> {
+        let content =
+            fs::read_to_string(path).map_err(|error| Error::Io(path.to_path_buf(), error))?;
+        toml::from_str(&content).map_err(|error| Error::Parse(path.to_path_buf(), error))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let content = toml::to_string(self).map_err(Error::Serialize)?;
+        fs::write(path, content).map_err(|error| Error::Io(path.to_path_buf(), error))
+    }
+
+    /// Creates a lockfile from the packages, their directories are recorded relative to the config.
+    /// Also returns the packages which were installed without recording their asset.
+    pub fn from_packages<'a>(
+        packages: &'a [Package],
+        config: &Config,
+    ) -> (Lockfile, Vec<&'a Package>) {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let mut locked_packages = Vec::new();
+        let mut skipped_packages = Vec::new();
+        for package in packages {
+            match LockedPackage::from_package(package, config, home.as_deref()) {
+                Some(locked_package) => locked_packages.push(locked_package),
+                None => skipped_packages.push(package),
+            }
+        }
+        let lockfile = Lockfile {
+            packages: locked_packages,
+        };
+        (lockfile, skipped_packages)
+    }
+}
+
+impl LockedPackage {
+    fn from_package(
+        package: &Package,
+        config: &Config,
+        home: Option<&Path>,
+    ) -> Option<LockedPackage> {
+        let path = Path::new(&package.path);
+        let prefix = config
+            .prefixes
+            .iter()
+            .filter(|(_, prefix_path)| prefix_path.as_path() == path)
+            .map(|(name, _)| name.to_string())
+            .min();
+        let path =
+            (prefix.is_none() && path != config.install_path).then(|| contract_home(path, home));
+        Some(LockedPackage {
+            name: package.name.to_string(),
+            repo: package.full_name.to_string(),
+            tag: package.version.to_string(),
+            asset: package.asset.clone()?,
+            url: package.url.clone()?,
+            sha256: package.sha256.clone()?,
+            binary: package.binary.to_string(),
+            prefix,
+            path,
+            source: package.source.clone(),
+            strip_components: package.strip_components,
+        })
+    }
+
+    /// Returns the directory to install the package to on this machine.
+    pub fn install_path(&self, config: &Config) -> Result<PathBuf, config::Error> {
+        let path = self
+            .path
+            .as_ref()
+            .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()));
+        config.resolve_install_path(self.prefix.as_deref(), path.as_deref())
+    }
+
+    /// Release the package is pinned to, without its assets.
+    pub fn repo_info(&self) -> RepoInfo {
+        RepoInfo {
+            name: self.name.to_string(),
+            full_name: self.repo.to_string(),
+            description: None,
+            version: self.tag.to_string(),
+            published_at: None,
+            assets: Vec::new(),
+        }
+    }
+}
+
+/// Replaces the home directory at the start of the path with `~`.
+fn contract_home(path: &Path, home: Option<&Path>) -> String {
+    match home.and_then(|home| path.strip_prefix(home).ok()) {
+        Some(relative) if relative.as_os_str().is_empty() => "~".to_string(),
+        Some(relative) => format!("~/{}", relative.display()),
+        None => path.to_string_lossy().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util;
+
+    fn package(name: &str, sha256: Option<&str>) -> Package {
+        Package {
+            asset: Some(format!("{}.tar.gz", name)),
+            url: Some(format!("https://example.com/{}.tar.gz", name)),
+            sha256: sha256.map(str::to_string),
+            source: Some(format!("{}-v1.0.0/{}", name, name)),
+            strip_components: 1,
+            ..test_util::package(name, Path::new("/test/path"))
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            token: None,
+            install_path: PathBuf::from("/home/alice/.local/bin"),
+            jobs: 1,
+            prefixes: [("work".to_string(), PathBuf::from("/opt/work/bin"))]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn from_packages() {
+        let packages = vec![package("locked", Some("abc")), package("legacy", None)];
+        let (lockfile, skipped) = Lockfile::from_packages(&packages, &config());
+        assert_eq!(lockfile.packages.len(), 1);
+        assert_eq!(lockfile.packages[0].repo, "owner/locked");
+        assert_eq!(lockfile.packages[0].sha256, "abc");
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].name, "legacy");
+    }
+
+    #[test]
+    fn save_load() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let (lockfile, _) = Lockfile::from_packages(&[package("tool", Some("abc"))], &config());
+        lockfile.save(file.path()).unwrap();
+        assert_eq!(Lockfile::load(file.path()).unwrap(), lockfile);
+    }
+
+    #[test]
+    fn relative_paths() {
+        let config = config();
+        let home = Some(Path::new("/home/alice"));
+        let mut package = package("tool", Some("abc"));
+        let mut lock = |path: &str| {
+            package.path = path.to_string();
+            LockedPackage::from_package(&package, &config, home).unwrap()
+        };
+
+        let locked = lock("/home/alice/.local/bin");
+        assert_eq!((locked.prefix, locked.path), (None, None));
+        let locked = lock("/opt/work/bin");
+        assert_eq!(
+            (locked.prefix.as_deref(), locked.path),
+            (Some("work"), None)
+        );
+        let locked = lock("/home/alice/tools");
+        assert_eq!(
+            (locked.prefix, locked.path.as_deref()),
+            (None, Some("~/tools"))
+        );
+        let locked = lock("/srv/bin");
+        assert_eq!(locked.path.as_deref(), Some("/srv/bin"));
+        assert_eq!(
+            locked.install_path(&config).unwrap(),
+            PathBuf::from("/srv/bin")
+        );
+    }
+
+    #[test]
+    fn install_path_of_prefix() {
+        let (lockfile, _) = Lockfile::from_packages(&[package("tool", Some("abc"))], &config());
+        let mut locked = lockfile.packages.into_iter().next().unwrap();
+        assert_eq!(
+            locked.install_path(&config()).unwrap(),
+            PathBuf::from("/test/path")
+        );
+        locked.path = None;
+        locked.prefix = Some("work".to_string());
+        assert_eq!(
+            locked.install_path(&config()).unwrap(),
+            PathBuf::from("/opt/work/bin")
+        );
+        locked.prefix = Some("missing".to_string());
+        assert!(locked.install_path(&config()).is_err());
+    }
+
+    #[test]
+    fn load_invalid_binary() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(
+            file.path(),
+            r#"
+            [[package]]
+            name = "bat"
+            repo = "sharkdp/bat"
+            tag = "v0.22.1"
+            asset = "bat.tar.gz"
+            url = "https://example.com/bat.tar.gz"
+            sha256 = "00"
+            binary = "../../.bashrc"
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(Lockfile::load(file.path()), Err(Error::Parse(..))));
+    }
+}
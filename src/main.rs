@@ -1,184 +1,2020 @@
+mod adopt;
 mod archive;
+mod cache;
 mod config;
 mod database;
+mod doctor;
+mod error;
+mod extras;
+mod hash;
+mod lockfile;
+mod manifest;
+mod parallel;
+mod platform;
 mod print;
+mod privilege;
+mod prompt;
 mod repo;
+mod search_path;
+mod selection;
+mod staging;
+#[cfg(test)]
+mod test_util;
+mod verify;
+mod version;
 
 use std::{
-    error::Error,
-    fs, io,
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsStr,
+    fs,
+    io::{self, IsTerminal},
     path::{Path, PathBuf},
+    process,
 };
 
-use clap::{command, Arg, ArgMatches, Command};
+use archive::ExtractOptions;
+use cache::{DownloadCache, HttpCache};
+use clap::{command, value_parser, Arg, ArgAction, ArgMatches, Command};
 use config::Config;
 use database::Database;
-use repo::RepoInfo;
+use doctor::{Finding, Severity};
+use error::Error;
+use lockfile::{LockedPackage, Lockfile};
+use manifest::Manifest;
+use print::{status, OutputFormat};
+use repo::{AssetInfo, Client, RepoInfo};
+use selection::{AssetPattern, AssetSelector};
+use staging::FileTransaction;
 use tempfile::NamedTempFile;
 
 use crate::database::Package;
 
+/// Exit code of `outdated` if updates are available.
+const EXIT_UPDATES_AVAILABLE: i32 = 100;
+
+/// Number of repositories searched when installing by search term.
+const SEARCH_LIMIT: usize = 10;
+
+/// Number of recent releases whose assets are compared with an adopted binary.
+const ADOPT_RELEASES: usize = 5;
+
+/// Number of cached releases kept for rollbacks besides the installed one.
+const KEPT_RELEASES: usize = 2;
+
+/// Each result costs a request, so `adopt --scan` only looks at the best matches.
+const SCAN_SEARCH_LIMIT: usize = 3;
+
 fn create_arg_matches() -> ArgMatches {
     command!()
+        .arg(
+            Arg::new("NonInteractive")
+                .short('y')
+                .long("yes")
+                .visible_alias("non-interactive")
+                .help("Never prompt, fail if a choice is ambiguous")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("System")
+                .long("system")
+                .help("Manage packages for all users with the configuration in /etc/grpm")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("DryRun")
+                .long("dry-run")
+                .help("Show what would change without downloading or writing anything")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("Offline")
+                .long("offline")
+                .help("Use only cached GitHub responses and downloads, fail if something is not cached")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("Output")
+                .short('o')
+                .long("output")
+                .help("Output format, machine readable formats keep other messages off stdout")
+                .value_parser(["table", "json", "yaml", "csv"])
+                .default_value("table")
+                .global(true),
+        )
         .subcommand(
-            Command::new("install").about("Installs a package").arg(
-                Arg::new("Repository")
-                    .help("Repository owner/repository")
-                    .required(true),
-            ),
+            Command::new("info")
+                .about("Shows a repository and the assets of its release")
+                .arg(
+                    Arg::new("Repository")
+                        .help("Repository owner/repository")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("Tag")
+                        .long("tag")
+                        .help("Release tag to show instead of the latest release"),
+                ),
         )
         .subcommand(
-            Command::new("uninstall").about("Uninstalls a package").arg(
-                Arg::new("Package")
-                    .help("The package to uninstall")
-                    .required(true),
-            ),
+            Command::new("search")
+                .about("Searches repositories with releases for this machine")
+                .arg(Arg::new("Term").help("Search term").required(true))
+                .arg(
+                    Arg::new("Limit")
+                        .long("limit")
+                        .help("Number of repositories to search")
+                        .default_value("10")
+                        .value_parser(value_parser!(usize)),
+                ),
+        )
+        .subcommand(
+            Command::new("install")
+                .about("Installs a package")
+                .arg(
+                    Arg::new("Repository")
+                        .help("Repository owner/repository, or a search term")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("Asset")
+                        .long("asset")
+                        .help("Glob or /regex/ matching the name of the asset to install")
+                        .conflicts_with("AssetIndex"),
+                )
+                .arg(
+                    Arg::new("AssetIndex")
+                        .long("asset-index")
+                        .help("Index of the asset to install")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("Tag")
+                        .long("tag")
+                        .help("Release tag to install instead of the latest release"),
+                )
+                .arg(
+                    Arg::new("Prefix")
+                        .long("prefix")
+                        .help("Name of a configured prefix to install to")
+                        .conflicts_with("InstallPath"),
+                )
+                .arg(
+                    Arg::new("InstallPath")
+                        .long("install-path")
+                        .help("Directory to install to")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("StripComponents")
+                        .long("strip-components")
+                        .help("Number of leading directories removed from the archive entries")
+                        .default_value("0")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("Alias")
+                        .long("as")
+                        .help("Name the package and its binary are installed as, e.g. batcat")
+                        .value_parser(parse_bin_name),
+                )
+                .arg(
+                    Arg::new("BinName")
+                        .long("bin-name")
+                        .help("Name the binary is installed as, also picks it from the archive")
+                        .value_parser(parse_bin_name),
+                ),
+        )
+        .subcommand(
+            Command::new("uninstall")
+                .about("Uninstalls packages")
+                .arg(
+                    Arg::new("Packages")
+                        .help("Names or owner/repository of the packages to uninstall")
+                        .required(true)
+                        .num_args(1..),
+                )
+                .arg(
+                    Arg::new("Purge")
+                        .long("purge")
+                        .help("Also remove the cached GitHub responses and downloads of the packages")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("rollback")
+                .about("Reinstalls the previous release of a package from the download cache")
+                .arg(
+                    Arg::new("Package")
+                        .help("Name or owner/repository of the package")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("Tag")
+                        .long("tag")
+                        .help("Cached release tag to install instead of the previous one"),
+                ),
         )
         .subcommand(Command::new("list").about("Lists all installed packages"))
-        .subcommand(Command::new("update").about("Updates all installed packages"))
+        .subcommand(
+            Command::new("update")
+                .about("Updates all installed packages")
+                .arg(jobs_arg()),
+        )
+        .subcommand(
+            Command::new("outdated")
+                .about("Lists packages with available updates, exits with 100 if there are any")
+                .arg(jobs_arg()),
+        )
+        .subcommand(
+            Command::new("sync")
+                .about("Installs and updates the packages listed in a manifest")
+                .arg(
+                    Arg::new("File")
+                        .short('f')
+                        .long("file")
+                        .help(
+                            "Manifest to sync with [default: $XDG_CONFIG_HOME/grpm/packages.toml]",
+                        )
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("Prune")
+                        .long("prune")
+                        .help("Uninstall packages which are not listed in the manifest")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Writes the installed packages to a lockfile")
+                .arg(lockfile_arg()),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Installs the exact packages of a lockfile")
+                .arg(lockfile_arg()),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Shows and changes the configuration")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("get")
+                        .about("Prints the value of a key")
+                        .arg(config_key_arg()),
+                )
+                .subcommand(
+                    Command::new("set")
+                        .about("Sets the value of a key, keeping the comments of the file")
+                        .arg(config_key_arg())
+                        .arg(Arg::new("Value").help("The new value").required(true)),
+                )
+                .subcommand(Command::new("list").about("Lists all values including defaults"))
+                .subcommand(Command::new("edit").about("Opens the config file in $EDITOR"))
+                .subcommand(Command::new("path").about("Prints the path of the config file"))
+                .subcommand(Command::new("validate").about("Checks the config file for errors")),
+        )
+        .subcommand(
+            Command::new("adopt")
+                .about("Records a binary installed without GRPM as package")
+                .arg(
+                    Arg::new("Repository")
+                        .help("Repository owner/repository the binary was released by")
+                        .required_unless_present("Scan"),
+                )
+                .arg(
+                    Arg::new("Binary")
+                        .long("binary")
+                        .help("Path of the binary [default: found in the install path or a prefix]")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("BinName")
+                        .long("bin-name")
+                        .help("Name of the binary to look for [default: the repository and asset names]")
+                        .value_parser(parse_bin_name)
+                        .conflicts_with("Binary"),
+                )
+                .arg(
+                    Arg::new("Tag")
+                        .long("tag")
+                        .help("Release tag of the binary instead of detecting it"),
+                )
+                .arg(
+                    Arg::new("Scan")
+                        .long("scan")
+                        .help("Suggest repositories for the untracked binaries in the install path")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["Repository", "Binary", "BinName", "Tag"]),
+                )
+                .arg(jobs_arg()),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Checks installed files against their checksums, exits with 11 on mismatches")
+                .arg(Arg::new("Package").help("The package to verify [default: all]"))
+                .arg(
+                    Arg::new("Upstream")
+                        .long("upstream")
+                        .help("Download the recorded assets again and compare the binaries")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(jobs_arg()),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Checks the configuration, token and installed packages, exits with 1 on problems")
+                .arg(
+                    Arg::new("Fix")
+                        .long("fix")
+                        .help("Repair drift between the database and the installed files")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new(privilege::FILE_OPERATION_COMMAND)
+                .hide(true)
+                .arg(
+                    Arg::new("Operation")
+                        .required(true)
+                        .value_parser(privilege::FILE_OPERATIONS),
+                )
+                .arg(
+                    Arg::new("Paths")
+                        .required(true)
+                        .num_args(1..=2)
+                        .value_parser(value_parser!(PathBuf)),
+                ),
+        )
         .get_matches()
 }
 
+fn jobs_arg() -> Arg {
+    Arg::new("Jobs")
+        .short('j')
+        .long("jobs")
+        .help("Number of packages checked and downloaded in parallel")
+        .value_parser(value_parser!(usize))
+}
+
+fn config_key_arg() -> Arg {
+    Arg::new("Key")
+        .help("One of token, install_path, jobs or prefixes.<name>")
+        .required(true)
+}
+
+fn lockfile_arg() -> Arg {
+    Arg::new("File")
+        .short('f')
+        .long("file")
+        .help("Path of the lockfile")
+        .default_value("grpm.lock")
+        .value_parser(value_parser!(PathBuf))
+}
+
+fn parse_bin_name(value: &str) -> Result<String, String> {
+    database::check_name(value)?;
+    Ok(value.to_string())
+}
+
 fn main() {
     let matches = create_arg_matches();
-    let config = Config::new();
-    let database = Database::new(Config::get_database_path()).unwrap();
 
+    // Privileged file operation re-executed through sudo/doas, must not touch the locked database
+    if let Some((privilege::FILE_OPERATION_COMMAND, subcommand)) = matches.subcommand() {
+        let operation = subcommand.get_one::<String>("Operation").unwrap();
+        let paths: Vec<PathBuf> = subcommand
+            .get_many::<PathBuf>("Paths")
+            .unwrap()
+            .cloned()
+            .collect();
+        if let Err(error) = privilege::run_file_operation(operation, &paths) {
+            exit_with(error.into());
+        }
+        return;
+    }
+
+    if let Err(error) = run(&matches) {
+        exit_with(error);
+    }
+}
+
+fn exit_with(error: Error) -> ! {
+    eprintln!("{}", error);
+    process::exit(error.exit_code());
+}
+
+fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let output_format = matches.get_one::<String>("Output").unwrap();
+    print::set_output_format(output_format.parse::<OutputFormat>().unwrap());
+    let dry_run = matches.get_flag("DryRun");
+    if dry_run {
+        if let Some(name) = matches
+            .subcommand_name()
+            .filter(|_| !supports_dry_run(matches))
+        {
+            return Err(Error::Unsupported {
+                option: "--dry-run",
+                command: name.to_string(),
+            });
+        }
+    }
+    let offline = matches.get_flag("Offline");
+    if offline {
+        if let Some(name) = matches
+            .subcommand_name()
+            .filter(|_| !supports_offline(matches))
+        {
+            return Err(Error::Unsupported {
+                option: "--offline",
+                command: name.to_string(),
+            });
+        }
+    }
+
+    // Work without loading the config, so a broken file can be diagnosed and repaired
     match matches.subcommand() {
-        Some(("install", subcommand)) => {
-            let repo = subcommand.get_one::<String>("Repository").unwrap();
-            if let Err(error) = install(
-                &database,
-                &repo,
-                &config.token.unwrap(),
-                config.install_path.as_ref(),
-            ) {
-                handle_error(error);
+        Some(("config", subcommand)) => {
+            Config::set_system_mode(matches.get_flag("System"));
+            return config(subcommand);
+        }
+        Some(("doctor", subcommand)) => {
+            Config::set_system_mode(matches.get_flag("System"));
+            if !doctor(subcommand.get_flag("Fix"), dry_run)? {
+                process::exit(error::EXIT_FAILURE);
             }
+            return Ok(());
         }
+        _ => {}
+    }
 
+    // Dry runs neither create the default folders nor migrate the database
+    let config = if dry_run {
+        Config::set_system_mode(matches.get_flag("System"));
+        Config::load()?
+    } else {
+        Config::new(matches.get_flag("System"))?
+    };
+    // The system-wide database may only be writable by root, reading it is enough for some commands
+    let read_only = dry_run
+        || matches!(
+            matches.subcommand_name(),
+            Some("info" | "search" | "list" | "outdated" | "verify" | "export")
+        );
+    let database_base_path = Config::get_database_base_path();
+    let writable = !Config::is_system_mode() || privilege::is_writable(&database_base_path);
+    if !writable && !read_only {
+        return Err(privilege::Error::NotWritable(database_base_path).into());
+    }
+    let database = if !writable || dry_run {
+        Database::snapshot(Config::get_database_path())?
+    } else {
+        Database::new(Config::get_database_path())?
+    };
+    let interactive = !matches.get_flag("NonInteractive");
+
+    match matches.subcommand() {
+        Some(("info", subcommand)) => {
+            let repo = subcommand.get_one::<String>("Repository").unwrap();
+            let tag = subcommand.get_one::<String>("Tag");
+            let client = create_client(&config, dry_run, offline);
+            info(&client, repo, tag.map(String::as_str))
+        }
+        Some(("search", subcommand)) => {
+            let term = subcommand.get_one::<String>("Term").unwrap();
+            let limit = *subcommand.get_one::<usize>("Limit").unwrap();
+            let client = create_client(&config, dry_run, offline);
+            search(&client, term, limit, config.jobs)
+        }
+        Some(("install", subcommand)) => {
+            let repo = subcommand.get_one::<String>("Repository").unwrap();
+            let tag = subcommand.get_one::<String>("Tag");
+            let client = create_client(&config, dry_run, offline);
+            let prefix = subcommand.get_one::<String>("Prefix");
+            let install_path = subcommand.get_one::<PathBuf>("InstallPath");
+            let alias = subcommand.get_one::<String>("Alias");
+            let options = InstallOptions {
+                install_path: config.resolve_install_path(
+                    prefix.map(String::as_str),
+                    install_path.map(PathBuf::as_path),
+                )?,
+                selector: create_asset_selector(subcommand)?,
+                extract: ExtractOptions {
+                    strip_components: *subcommand.get_one::<usize>("StripComponents").unwrap(),
+                    bin_name: subcommand.get_one::<String>("BinName").or(alias).cloned(),
+                    source: None,
+                },
+                alias: alias.cloned(),
+                interactive,
+                dry_run,
+                offline,
+            };
+            let tag = tag.map(String::as_str);
+            install(&database, &client, repo, tag, &options, config.jobs)
+        }
         Some(("uninstall", subcommand)) => {
+            let package_names: Vec<&str> = subcommand
+                .get_many::<String>("Packages")
+                .unwrap()
+                .map(String::as_str)
+                .collect();
+            let options = UninstallOptions {
+                dry_run,
+                purge: subcommand.get_flag("Purge"),
+                interactive,
+            };
+            uninstall(&database, &package_names, &options)
+        }
+        Some(("rollback", subcommand)) => {
             let package_name = subcommand.get_one::<String>("Package").unwrap();
-            if let Err(error) = uninstall(&database, &package_name) {
-                handle_error(error);
+            let tag = subcommand.get_one::<String>("Tag");
+            rollback(
+                &database,
+                package_name,
+                tag.map(String::as_str),
+                interactive,
+                dry_run,
+            )
+        }
+        Some(("list", _)) => list(&database),
+        Some(("update", subcommand)) => {
+            let client = create_client(&config, dry_run, offline);
+            let jobs = subcommand
+                .get_one::<usize>("Jobs")
+                .copied()
+                .unwrap_or(config.jobs);
+            update(&database, &client, jobs, interactive, dry_run)
+        }
+        Some(("outdated", subcommand)) => {
+            let client = create_client(&config, dry_run, offline);
+            let jobs = subcommand
+                .get_one::<usize>("Jobs")
+                .copied()
+                .unwrap_or(config.jobs);
+            if outdated(&database, &client, jobs)? {
+                process::exit(EXIT_UPDATES_AVAILABLE);
             }
+            Ok(())
         }
-        Some(("list", _)) => {
-            if let Err(error) = list(&database) {
-                handle_error(error);
+        Some(("sync", subcommand)) => {
+            let client = create_client(&config, dry_run, offline);
+            let manifest_path = subcommand
+                .get_one::<PathBuf>("File")
+                .cloned()
+                .unwrap_or_else(Config::get_manifest_path);
+            let prune = subcommand.get_flag("Prune");
+            sync(
+                &database,
+                &client,
+                &config,
+                &manifest_path,
+                prune,
+                interactive,
+                dry_run,
+            )
+        }
+        Some(("adopt", subcommand)) => {
+            let client = create_client(&config, dry_run, offline);
+            let jobs = subcommand
+                .get_one::<usize>("Jobs")
+                .copied()
+                .unwrap_or(config.jobs);
+            if subcommand.get_flag("Scan") {
+                return scan(&database, &client, &config.install_path, jobs);
             }
+            let repo = subcommand.get_one::<String>("Repository").unwrap();
+            let binary = subcommand.get_one::<PathBuf>("Binary");
+            let bin_name = subcommand.get_one::<String>("BinName");
+            let tag = subcommand.get_one::<String>("Tag");
+            adopt(
+                &database,
+                &client,
+                repo,
+                AdoptOptions {
+                    binary: binary.map(PathBuf::as_path),
+                    bin_name: bin_name.map(String::as_str),
+                    tag: tag.map(String::as_str),
+                    directories: install_directories(&config),
+                    interactive,
+                },
+                jobs,
+            )
         }
-        Some(("update", _)) => {
-            if let Err(error) = update(&database, &config.token.unwrap()) {
-                handle_error(error);
+        Some(("verify", subcommand)) => {
+            let package_name = subcommand.get_one::<String>("Package");
+            let upstream = subcommand.get_flag("Upstream");
+            let jobs = subcommand
+                .get_one::<usize>("Jobs")
+                .copied()
+                .unwrap_or(config.jobs);
+            if !verify(&database, package_name.map(String::as_str), upstream, jobs)? {
+                process::exit(error::EXIT_INTEGRITY);
             }
+            Ok(())
         }
-        _ => {}
+        Some(("export", subcommand)) => {
+            let lockfile_path = subcommand.get_one::<PathBuf>("File").unwrap();
+            export(&database, &config, lockfile_path)
+        }
+        Some(("import", subcommand)) => {
+            let lockfile_path = subcommand.get_one::<PathBuf>("File").unwrap();
+            import(&database, &config, lockfile_path, dry_run, offline)
+        }
+        _ => Ok(()),
+    }
+}
+
+struct UninstallOptions {
+    dry_run: bool,
+    purge: bool,
+    interactive: bool,
+}
+
+struct InstallOptions {
+    install_path: PathBuf,
+    selector: AssetSelector,
+    extract: ExtractOptions,
+    /// Name of the package instead of the repository name.
+    alias: Option<String>,
+    interactive: bool,
+    /// Only report what would change.
+    dry_run: bool,
+    /// Only install cached downloads.
+    offline: bool,
+}
+
+/// Returns false for commands which write something but can not only show it.
+fn supports_dry_run(matches: &ArgMatches) -> bool {
+    match matches.subcommand() {
+        Some(("config", subcommand)) => {
+            !matches!(subcommand.subcommand_name(), Some("set" | "edit"))
+        }
+        Some(("doctor", subcommand)) => !subcommand.get_flag("Fix"),
+        Some(("adopt" | "export", _)) => false,
+        _ => true,
+    }
+}
+
+/// Returns false for commands which need GitHub beyond the cached responses and downloads.
+fn supports_offline(matches: &ArgMatches) -> bool {
+    match matches.subcommand() {
+        Some(("verify", subcommand)) => !subcommand.get_flag("Upstream"),
+        Some(("adopt" | "doctor", _)) => false,
+        _ => true,
     }
 }
 
-fn handle_error(error: Box<dyn Error>) {
-    if let Some(error) = error.downcast_ref::<attohttpc::Error>() {
-        eprintln!("HTTP Error: {}", error);
-    } else if let Some(err) = error.downcast_ref::<io::Error>() {
-        eprintln!("I/O Error: {}", err);
-    } else if let Some(err) = error.downcast_ref::<jammdb::Error>() {
-        eprintln!("Database Error: {}", err);
+fn create_client(config: &Config, dry_run: bool, offline: bool) -> Client {
+    let cache_path = Config::get_http_cache_path();
+    let cache = if dry_run || !Config::is_cache_writable() {
+        HttpCache::read_only(cache_path)
     } else {
-        eprintln!("Unknown Error: {}", error);
+        HttpCache::new(cache_path)
+    };
+    Client::new(config.token.clone(), cache, offline)
+}
+
+fn create_asset_selector(subcommand: &ArgMatches) -> Result<AssetSelector, Error> {
+    if let Some(index) = subcommand.get_one::<usize>("AssetIndex") {
+        return Ok(AssetSelector::Index(*index));
     }
+    if let Some(pattern) = subcommand.get_one::<String>("Asset") {
+        return Ok(AssetSelector::Pattern(AssetPattern::parse(pattern)?));
+    }
+    Ok(AssetSelector::Any)
 }
 
-fn update(database: &Database, token: &str) -> Result<(), Box<dyn Error>> {
+/// Returns the packages with a newer release and the full names of those which could not be
+/// checked, a renamed repository must not keep the other packages from being updated.
+fn check_updates(
+    installed_packages: Vec<Package>,
+    client: &Client,
+    jobs: usize,
+) -> (Vec<(Package, RepoInfo)>, Vec<String>) {
+    status!("Checking {} packages...", installed_packages.len());
+    let repo_infos = parallel::map(&installed_packages, jobs, |package| {
+        client.get_repo_infos(&package.full_name)
+    });
 
-    let installed_packages = database.get_all()?;
     let mut updateable_packages: Vec<(Package, RepoInfo)> = Vec::new();
+    let mut failed_packages = Vec::new();
+    for (package, repo_info) in installed_packages.into_iter().zip(repo_infos) {
+        match repo_info {
+            Ok(repo_info) if version::is_newer(&package.version, &repo_info.version) => {
+                updateable_packages.push((package, repo_info))
+            }
+            Ok(_) => {}
+            Err(error) => {
+                eprintln!("Could not check {}: {}", package.full_name, error);
+                failed_packages.push(package.full_name);
+            }
+        }
+    }
+    (updateable_packages, failed_packages)
+}
+
+/// Fails with the packages which were skipped because of an error reported before.
+fn ensure_none_failed(failed_packages: Vec<String>) -> Result<(), Error> {
+    if failed_packages.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Incomplete(failed_packages))
+    }
+}
+
+/// Returns true if updates are available.
+fn outdated(database: &Database, client: &Client, jobs: usize) -> Result<bool, Error> {
+    let installed_packages = database.get_all()?;
+    let (updateable_packages, failed_packages) = check_updates(installed_packages, client, jobs);
+    if updateable_packages.is_empty() && !print::is_machine_readable() {
+        if failed_packages.is_empty() {
+            status!("All packages are up to date");
+        }
+    } else {
+        print::print_updates(&updateable_packages)?;
+    }
+    ensure_none_failed(failed_packages)?;
+    Ok(!updateable_packages.is_empty())
+}
+
+fn update(
+    database: &Database,
+    client: &Client,
+    jobs: usize,
+    interactive: bool,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let installed_packages = database.get_all()?;
     if installed_packages.is_empty() {
-        println!("No packages installed yet");
+        status!("No packages installed yet");
         return Ok(());
     }
 
-    for package in installed_packages {
-        let repo_info = repo::get_repo_infos(&package.full_name, token)?;
-        if package.version == repo_info.version {
-            updateable_packages.push((package, repo_info));
+    let (updateable_packages, mut failed_packages) =
+        check_updates(installed_packages, client, jobs);
+    if updateable_packages.is_empty() {
+        if print::is_machine_readable() {
+            print::print_updates(&updateable_packages)?;
         }
+        status!("No updates available");
+        return ensure_none_failed(failed_packages);
     }
 
-    if updateable_packages.is_empty() {
-        println!("No updates available");
+    print::print_updates(&updateable_packages)?;
+    status!();
+
+    let mut downloads = Vec::new();
+    for (package, repo_info) in &updateable_packages {
+        let prepared = prepare_update(database, package, repo_info, interactive, dry_run);
+        match prepared {
+            Ok(Some(asset)) => downloads.push((package, repo_info, asset, NamedTempFile::new()?)),
+            Ok(None) => {}
+            Err(Error::Cancelled) => return Err(Error::Cancelled),
+            Err(error) => {
+                eprintln!("Could not update {}: {}", package.full_name, error);
+                failed_packages.push(package.full_name.to_string());
+            }
+        }
+    }
+
+    if dry_run {
+        return ensure_none_failed(failed_packages);
+    }
+
+    status!("Downloading {} assets...", downloads.len());
+    let download_cache = DownloadCache::new(Config::get_download_cache_path());
+    let download_results = parallel::map(
+        &downloads,
+        jobs,
+        |(_, repo_info, asset, tmp_download_file)| {
+            download_cache.fetch(
+                &repo_info.full_name,
+                &repo_info.version,
+                asset,
+                None,
+                tmp_download_file.path(),
+                client.is_offline(),
+            )
+        },
+    );
+
+    for ((package, repo_info, asset, tmp_download_file), download_result) in
+        downloads.iter().zip(download_results)
+    {
+        if let Err(error) = download_result {
+            eprintln!("Failed to download {}: {}", asset.name, error);
+            failed_packages.push(package.full_name.to_string());
+            continue;
+        }
+        status!("Updating {} to {}...", package.name, repo_info.version);
+        let installed = install_asset(
+            database,
+            repo_info,
+            &package.name,
+            asset,
+            tmp_download_file.path(),
+            Path::new(&package.path),
+            &ExtractOptions {
+                strip_components: package.strip_components,
+                bin_name: Some(package.binary.to_string()),
+                source: package.source.clone(),
+            },
+        );
+        if let Err(error) = installed {
+            eprintln!("Failed to update {}: {}", package.full_name, error);
+            failed_packages.push(package.full_name.to_string());
+        }
+    }
+
+    status!("Done!");
+    ensure_none_failed(failed_packages)
+}
+
+/// Chooses the asset to update a package with, `None` for dry runs which only describe the update.
+fn prepare_update<'a>(
+    database: &Database,
+    package: &Package,
+    repo_info: &'a RepoInfo,
+    interactive: bool,
+    dry_run: bool,
+) -> Result<Option<&'a AssetInfo>, Error> {
+    privilege::ensure_placeable(Path::new(&package.path))?;
+    print::print_repo_info(repo_info);
+    // Packages installed before the asset was recorded are chosen from the builds for this machine
+    let selector = AssetSelector::Previous(
+        package
+            .asset
+            .as_deref()
+            .map(|asset| selection::renamed_asset(asset, &package.version, &repo_info.version))
+            .unwrap_or_default(),
+    );
+    let choosen_asset_index = selection::select_asset(&repo_info.assets, &selector, interactive)?;
+    let asset = &repo_info.assets[choosen_asset_index];
+    if dry_run {
+        describe_install(
+            database,
+            repo_info,
+            asset,
+            Path::new(&package.path),
+            Some(&package.binary),
+        )?;
+        return Ok(None);
+    }
+    Ok(Some(asset))
+}
+
+fn config(subcommand: &ArgMatches) -> Result<(), Error> {
+    let config_path = Config::get_config_path();
+    match subcommand.subcommand() {
+        Some(("get", arguments)) => {
+            let key = arguments.get_one::<String>("Key").unwrap();
+            let config = Config::load()?;
+            if let Some(value) = config.get(key)? {
+                println!("{}", value);
+            }
+            Ok(())
+        }
+        Some(("set", arguments)) => {
+            let key = arguments.get_one::<String>("Key").unwrap();
+            let value = arguments.get_one::<String>("Value").unwrap();
+            Config::set(key, value)?;
+            status!("Set {} in {}", key, config_path.display());
+            Ok(())
+        }
+        Some(("list", _)) => {
+            let config = Config::load()?;
+            print::print_config(&config.entries())?;
+            Ok(())
+        }
+        Some(("edit", _)) => Ok(Config::edit()?),
+        Some(("path", _)) => {
+            println!("{}", config_path.display());
+            Ok(())
+        }
+        Some(("validate", _)) => {
+            Config::validate()?;
+            status!("{} is valid", config_path.display());
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+struct AdoptOptions<'a> {
+    binary: Option<&'a Path>,
+    bin_name: Option<&'a str>,
+    tag: Option<&'a str>,
+    /// Install path and prefixes, the only directories searched for the binary.
+    directories: Vec<PathBuf>,
+    interactive: bool,
+}
+
+/// Install path followed by the configured prefixes.
+fn install_directories(config: &Config) -> Vec<PathBuf> {
+    let mut prefixes: Vec<&PathBuf> = config.prefixes.values().collect();
+    prefixes.sort();
+    std::iter::once(&config.install_path)
+        .chain(prefixes)
+        .cloned()
+        .collect()
+}
+
+/// Creates the record of a binary which was installed by hand. The version is taken from `--tag`,
+/// detected from the `--version` output or found by comparing the binary with the release assets.
+fn adopt(
+    database: &Database,
+    client: &Client,
+    repo: &str,
+    options: AdoptOptions,
+    jobs: usize,
+) -> Result<(), Error> {
+    let tag = options.tag;
+    let mut releases = client.get_releases(repo, ADOPT_RELEASES)?;
+    if let Some(tag) = tag {
+        releases.retain(|release| release.version == tag);
+        if releases.is_empty() {
+            releases.push(client.get_repo_infos_for_tag(repo, Some(tag))?);
+        }
+    }
+    let Some(name) = releases.first().map(|release| release.name.to_string()) else {
+        return Err(repo::Error::NotFound(format!("A release of {}", repo)).into());
+    };
+    if let Some(package) = database.get(&database::key(repo))? {
+        return Err(adopt::Error::AlreadyInstalled {
+            name,
+            path: package.path,
+        }
+        .into());
+    }
+
+    let binary_path = match options.binary {
+        // Updates replace the binary, which must not happen to files of the system package manager
+        Some(binary) => {
+            if !adopt::is_in_directories(binary, &options.directories) {
+                eprintln!(
+                    "{} is not in the install path or a prefix, updates will replace it there",
+                    binary.display()
+                );
+                if options.interactive && io::stdin().is_terminal() {
+                    prompt::confirm(&format!("Adopt {}?", binary.display()))?;
+                }
+            }
+            binary.to_path_buf()
+        }
+        None => {
+            let names = match options.bin_name {
+                Some(bin_name) => vec![bin_name.to_string()],
+                None => adopt::binary_names(&name, &releases[0].assets),
+            };
+            adopt::find_binary(&names, &options.directories)
+                .ok_or(adopt::Error::BinaryNotFound(names))?
+        }
+    };
+    let binary_sha256 = hash::sha256_file(&binary_path)?;
+
+    let mut version_known = tag.is_some();
+    if !version_known {
+        let tags: Vec<String> = releases
+            .iter()
+            .map(|release| release.version.to_string())
+            .collect();
+        let detected = adopt::version_output(&binary_path)
+            .and_then(|output| version::find_tag(&output, &tags).map(str::to_string));
+        if let Some(detected) = detected {
+            status!("Detected version {} of {}", detected, binary_path.display());
+            releases.retain(|release| release.version == detected);
+            version_known = true;
+        }
+    }
+
+    // Looks for the binary in the assets for this machine, newest release first
+    let mut matched = None;
+    for release in &releases {
+        let assets: Vec<(&AssetInfo, NamedTempFile)> = release
+            .assets
+            .iter()
+            .filter(|asset| platform::matches_host(&asset.name))
+            .map(|asset| Ok((asset, NamedTempFile::new()?)))
+            .collect::<Result<_, Error>>()?;
+        status!(
+            "Comparing {} with {} assets of {}...",
+            binary_path.display(),
+            assets.len(),
+            release.version
+        );
+        let sources = parallel::map(&assets, jobs, |(asset, tmp_download_file)| {
+            repo::download_asset(asset, tmp_download_file.path())
+                .ok()
+                .and_then(|()| adopt::find_in_asset(tmp_download_file.path(), &binary_sha256))
+        });
+        let found = assets
+            .iter()
+            .zip(sources)
+            .find_map(|((asset, tmp_download_file), source)| {
+                Some((
+                    *asset,
+                    hash::sha256_file(tmp_download_file.path()).ok()?,
+                    source?,
+                ))
+            });
+        if let Some((asset, sha256, source)) = found {
+            matched = Some((release, asset, sha256, source));
+            break;
+        }
+    }
+
+    let package = match matched {
+        Some((release, asset, sha256, source)) => {
+            status!("Found {} in {}", binary_path.display(), asset.name);
+            create_adopted_package(
+                release,
+                &binary_path,
+                &binary_sha256,
+                Some((asset, sha256, source)),
+            )
+        }
+        // Without a matching asset only an explicit or detected version is trusted
+        None if version_known => {
+            eprintln!(
+                "{} matches no asset of {} {}, it can not be updated in place or verified upstream",
+                binary_path.display(),
+                repo,
+                releases[0].version
+            );
+            create_adopted_package(&releases[0], &binary_path, &binary_sha256, None)
+        }
+        None => return Err(adopt::Error::UnknownVersion(binary_path).into()),
+    };
+
+    database.put(&package.key(), &package)?;
+    status!(
+        "Adopted {} {} at {}",
+        package.full_name,
+        package.version,
+        binary_path.display()
+    );
+    Ok(())
+}
+
+fn create_adopted_package(
+    release: &RepoInfo,
+    binary_path: &Path,
+    binary_sha256: &str,
+    asset: Option<(&AssetInfo, String, String)>,
+) -> Package {
+    let (asset, sha256, source) = match asset {
+        Some((asset, sha256, source)) => (Some(asset), Some(sha256), Some(source)),
+        None => (None, None, None),
+    };
+    Package {
+        name: release.name.to_string(),
+        full_name: release.full_name.to_string(),
+        version: release.version.to_string(),
+        binary: binary_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+        path: binary_path
+            .parent()
+            .unwrap_or(Path::new("/"))
+            .to_string_lossy()
+            .to_string(),
+        asset: asset.map(|asset| asset.name.to_string()),
+        url: asset.map(|asset| asset.url.to_string()),
+        sha256,
+        source,
+        strip_components: 0,
+        files: Vec::new(),
+        checksums: BTreeMap::from([(
+            binary_path.to_string_lossy().to_string(),
+            binary_sha256.to_string(),
+        )]),
+    }
+}
+
+/// Suggests repositories for the executables in the install path which belong to no package.
+fn scan(
+    database: &Database,
+    client: &Client,
+    install_path: &Path,
+    jobs: usize,
+) -> Result<(), Error> {
+    let packages = database.get_all()?;
+    let executables = adopt::untracked_executables(install_path, &packages);
+    if executables.is_empty() && !print::is_machine_readable() {
+        status!("No untracked binaries in {}", install_path.display());
         return Ok(());
     }
 
-    print::print_updates(&updateable_packages);
+    let mut suggestions = Vec::new();
+    for executable in executables {
+        let name = executable
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        status!("Searching {}...", name);
+        let results = match client.search(&name, SCAN_SEARCH_LIMIT, jobs) {
+            Ok(results) => results,
+            Err(error) => {
+                eprintln!("Could not search {}: {}", name, error);
+                Vec::new()
+            }
+        };
+        // A repository named like the binary is the most likely origin
+        let repository = results
+            .iter()
+            .find(|result| {
+                result
+                    .full_name
+                    .rsplit('/')
+                    .next()
+                    .is_some_and(|repo| repo.eq_ignore_ascii_case(&name))
+            })
+            .or(results.first())
+            .map(|result| result.full_name.to_string());
+        suggestions.push(adopt::Suggestion {
+            binary: executable.to_string_lossy().to_string(),
+            repository,
+        });
+    }
+    print::print_suggestions(&suggestions)?;
     Ok(())
 }
 
-fn list(database: &Database) -> Result<(), Box<dyn Error>> {
+/// Prints the state of every installed file, returns false if any was tampered with or is missing.
+fn verify(
+    database: &Database,
+    package_name: Option<&str>,
+    upstream: bool,
+    jobs: usize,
+) -> Result<bool, Error> {
+    let packages = match package_name {
+        Some(package_name) => match database.find(package_name)? {
+            Some(package) => vec![package],
+            None => {
+                return Err(database::Error::NotInstalled(vec![package_name.to_string()]).into())
+            }
+        },
+        None => database.get_all()?,
+    };
+
+    let mut reports: Vec<verify::FileReport> =
+        packages.iter().flat_map(verify::verify_files).collect();
+
+    if upstream {
+        let mut downloads = Vec::new();
+        for package in &packages {
+            let (Some(asset), Some(url)) = (&package.asset, &package.url) else {
+                eprintln!(
+                    "Skipping {}, its asset is unknown. Reinstall it to verify it upstream",
+                    package.name
+                );
+                continue;
+            };
+            let asset = AssetInfo {
+                name: asset.to_string(),
+                url: url.to_string(),
+                size: 0,
+                download_count: 0,
+            };
+            downloads.push((package, asset, NamedTempFile::new()?));
+        }
+
+        status!("Downloading {} assets...", downloads.len());
+        let upstream_reports =
+            parallel::map(
+                &downloads,
+                jobs,
+                |(package, asset, tmp_file)| match repo::download_asset(asset, tmp_file.path()) {
+                    Ok(()) => verify::verify_upstream(package, tmp_file.path()),
+                    Err(error) => verify::download_failed(package, &error),
+                },
+            );
+        reports.extend(upstream_reports);
+    }
+
+    print::print_file_reports(&reports)?;
+    Ok(!reports.iter().any(verify::FileReport::is_failure))
+}
+
+/// Prints the result of all checks, returns false if problems remain.
+/// A dry run neither creates folders, migrates the database nor caches responses.
+fn doctor(fix: bool, dry_run: bool) -> Result<bool, Error> {
+    let mut findings = Vec::new();
+    let config_path = Config::get_config_path();
+    let config = match Config::load() {
+        Ok(config) => {
+            findings.push(Finding::ok(
+                "config",
+                format!("{} is valid", config_path.display()),
+            ));
+            config
+        }
+        Err(error) => {
+            findings.push(Finding::problem(
+                "config",
+                error.to_string(),
+                "Run `grpm config edit` to correct it",
+            ));
+            Config::default()
+        }
+    };
+
+    let client = create_client(&config, dry_run, false);
+    findings.push(doctor::check_token(
+        config.token.is_some(),
+        client.get_api_status(),
+    ));
+
+    let directories = search_path::directories();
+    findings.extend(doctor::check_install_path(
+        &config.install_path,
+        &directories,
+    ));
+
+    let database_path = Config::get_database_path();
+    let database = if dry_run {
+        Database::snapshot(&database_path)
+    } else {
+        // A failure is reported by opening the database below
+        let _ = Config::create_default_folders();
+        Database::new(&database_path)
+    };
+    let database = match database {
+        Ok(database) => database,
+        Err(error) => {
+            findings.push(Finding::problem(
+                "database",
+                format!("{}: {}", database_path.display(), error),
+                "Check the permissions of the database",
+            ));
+            print::print_findings(&findings)?;
+            return Ok(false);
+        }
+    };
+    let packages = match database.get_all() {
+        Ok(packages) => packages,
+        Err(error) => {
+            findings.push(Finding::problem(
+                "database",
+                error.to_string(),
+                "Reinstall the packages after removing the database",
+            ));
+            Vec::new()
+        }
+    };
+    for package in &packages {
+        findings.extend(doctor::check_package(package, &directories));
+    }
+    findings.extend(doctor::check_collisions(&packages));
+
+    let mut install_paths: BTreeSet<PathBuf> = packages
+        .iter()
+        .map(|package| PathBuf::from(&package.path))
+        .collect();
+    install_paths.insert(config.install_path.clone());
+    findings.extend(doctor::check_untracked(
+        &packages,
+        &install_paths,
+        &directories,
+    ));
+    findings.extend(doctor::check_leftovers(&install_paths));
+
+    if fix {
+        for finding in &mut findings {
+            let Some(repair) = &finding.repair else {
+                continue;
+            };
+            match doctor::repair(&database, repair) {
+                Ok(()) => finding.severity = Severity::Fixed,
+                Err(error) => eprintln!("Could not fix {}: {}", finding.message, error),
+            }
+        }
+    }
+
+    print::print_findings(&findings)?;
+    let healthy = findings
+        .iter()
+        .all(|finding| finding.severity != Severity::Problem);
+    Ok(healthy)
+}
+
+fn list(database: &Database) -> Result<(), Error> {
     let packages = database.get_all()?;
-    if packages.is_empty() {
-        println!("No packages installed yet");
+    if packages.is_empty() && !print::is_machine_readable() {
+        status!("No packages installed yet");
     } else {
-        print::print_packages(&packages);
+        print::print_packages(&packages)?;
+    }
+    Ok(())
+}
+
+fn info(client: &Client, repo: &str, tag: Option<&str>) -> Result<(), Error> {
+    let repo_info = client.get_repo_infos_for_tag(repo, tag)?;
+    print::print_repo(&repo_info)?;
+    Ok(())
+}
+
+fn sync(
+    database: &Database,
+    client: &Client,
+    config: &Config,
+    manifest_path: &Path,
+    prune: bool,
+    interactive: bool,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let manifest = Manifest::load(manifest_path)?;
+    let installed_packages = database.get_all()?;
+
+    status!("Checking {} packages...", manifest.packages.len());
+    let repo_infos = parallel::map(&manifest.packages, config.jobs, |entry| {
+        client.get_repo_infos_for_tag(&entry.repo, entry.tag.as_deref())
+    });
+
+    for (entry, repo_info) in manifest.packages.iter().zip(repo_infos) {
+        let repo_info = repo_info?;
+        let selector = match &entry.asset {
+            Some(pattern) => AssetSelector::Pattern(AssetPattern::parse(pattern)?),
+            None => AssetSelector::Any,
+        };
+        let installed_package = installed_packages
+            .iter()
+            .find(|package| package.full_name.eq_ignore_ascii_case(&entry.repo));
+        // An alias given at installation is kept unless the manifest names the package
+        let alias = entry
+            .alias
+            .clone()
+            .or_else(|| installed_package.map(|package| package.name.to_string()));
+        let options = InstallOptions {
+            install_path: config
+                .resolve_install_path(entry.prefix.as_deref(), entry.install_path().as_deref())?,
+            selector,
+            extract: ExtractOptions {
+                strip_components: entry.strip_components.unwrap_or(0),
+                bin_name: entry.binary.clone().or(entry.alias.clone()),
+                source: None,
+            },
+            alias,
+            interactive,
+            dry_run,
+            offline: client.is_offline(),
+        };
+        match installed_package {
+            None => {
+                status!("Installing {} {}...", entry.repo, repo_info.version);
+                install_release(database, &repo_info, &options)?;
+            }
+            Some(package) if is_drifted(package, &repo_info, &options) => {
+                status!(
+                    "Updating {} from {} to {}...",
+                    entry.repo,
+                    package.version,
+                    repo_info.version
+                );
+                install_release(database, &repo_info, &options)?;
+                let old_path = PathBuf::from(&package.path).join(&package.binary);
+                if dry_run {
+                    if options.install_path.join(&package.binary) != old_path && old_path.exists() {
+                        status!("Would remove {}", old_path.display());
+                    }
+                    continue;
+                }
+                let new_path = database
+                    .get(&package.key())?
+                    .map(|new_package| PathBuf::from(new_package.path).join(new_package.binary));
+                if new_path.is_some_and(|new_path| new_path != old_path) && old_path.exists() {
+                    privilege::remove_file(&old_path)?;
+                }
+            }
+            Some(_) => status!("{} {} is up to date", entry.repo, repo_info.version),
+        }
+    }
+
+    if prune {
+        for package in installed_packages
+            .iter()
+            .filter(|package| !manifest.contains(&package.full_name))
+        {
+            status!("Uninstalling {}...", package.full_name);
+            remove_package(database, package, dry_run)?;
+        }
+    }
+
+    status!("Done!");
+    Ok(())
+}
+
+fn is_drifted(package: &Package, repo_info: &RepoInfo, options: &InstallOptions) -> bool {
+    let binary_drifted = options
+        .extract
+        .bin_name
+        .as_ref()
+        .is_some_and(|bin_name| bin_name != &package.binary);
+    let alias_drifted = options
+        .alias
+        .as_ref()
+        .is_some_and(|alias| alias != &package.name);
+    package.version != repo_info.version
+        || Path::new(&package.path) != options.install_path
+        || binary_drifted
+        || alias_drifted
+}
+
+fn search(client: &Client, term: &str, limit: usize, jobs: usize) -> Result<(), Error> {
+    let results = client.search(term, limit, jobs)?;
+    if results.is_empty() && !print::is_machine_readable() {
+        status!("No repository with a release for this machine found");
+    } else {
+        print::print_search_results(&results)?;
     }
     Ok(())
 }
 
 fn install(
     database: &Database,
+    client: &Client,
     repo: &str,
-    token: &str,
-    install_path: &Path,
-) -> Result<(), Box<dyn Error>> {
-    let tmp_download_file = NamedTempFile::new()?;
-    let tmp_decompress_file = NamedTempFile::new()?;
+    tag: Option<&str>,
+    options: &InstallOptions,
+    jobs: usize,
+) -> Result<(), Error> {
+    let full_name = if repo.contains('/') {
+        repo.to_string()
+    } else {
+        status!("Searching {}...", repo);
+        let mut results = client.search(repo, SEARCH_LIMIT, jobs)?;
+        let index = selection::select_repository(&results, repo, options.interactive)?;
+        results.swap_remove(index).full_name
+    };
 
-    let repo_info = repo::get_repo_infos(repo, token)?;
+    let repo_info = client.get_repo_infos_for_tag(&full_name, tag)?;
     print::print_repo_info(&repo_info);
-    let choosen_asset_index = print::print_index_question("Choose an asset to download");
+    install_release(database, &repo_info, options)?;
+    status!("Done!");
+    Ok(())
+}
+
+fn install_release(
+    database: &Database,
+    repo_info: &RepoInfo,
+    options: &InstallOptions,
+) -> Result<(), Error> {
+    privilege::ensure_placeable(&options.install_path)?;
+    let choosen_asset_index =
+        selection::select_asset(&repo_info.assets, &options.selector, options.interactive)?;
     let asset = &repo_info.assets[choosen_asset_index];
+    if options.dry_run {
+        return describe_install(
+            database,
+            repo_info,
+            asset,
+            &options.install_path,
+            options.extract.bin_name.as_deref(),
+        );
+    }
 
-    println!("Downloading {}...", asset.name);
-    repo::download_asset(asset, tmp_download_file.path())?;
+    status!("Downloading {}...", asset.name);
+    // The checksum is only known when the same asset is installed again
+    let installed_sha256 = database
+        .get(&database::key(&repo_info.full_name))?
+        .filter(|package| {
+            package.version == repo_info.version && package.asset.as_ref() == Some(&asset.name)
+        })
+        .and_then(|package| package.sha256);
+    let tmp_download_file = NamedTempFile::new()?;
+    DownloadCache::new(Config::get_download_cache_path()).fetch(
+        &repo_info.full_name,
+        &repo_info.version,
+        asset,
+        installed_sha256.as_deref(),
+        tmp_download_file.path(),
+        options.offline,
+    )?;
 
-    println!("Decompressing {}...", asset.name);
-    archive::decompress_file(tmp_download_file.path(), tmp_decompress_file.path());
+    install_asset(
+        database,
+        repo_info,
+        options.alias.as_deref().unwrap_or(&repo_info.name),
+        asset,
+        tmp_download_file.path(),
+        &options.install_path,
+        &options.extract,
+    )
+}
 
-    println!("Reading {}...", asset.name);
-    let tar_infos = archive::get_tar_infos(tmp_decompress_file.path());
+/// Reports what installing the asset would change. The archive is not downloaded, so the binary
+/// name is only known if it is given or the package is already installed.
+fn describe_install(
+    database: &Database,
+    repo_info: &RepoInfo,
+    asset: &AssetInfo,
+    install_path: &Path,
+    bin_name: Option<&str>,
+) -> Result<(), Error> {
+    let key = database::key(&repo_info.full_name);
+    let previous_package = database.get(&key)?;
+    let cache = DownloadCache::new(Config::get_download_cache_path());
+    // Lockfiles do not record the size
+    if cache
+        .get(&repo_info.full_name, &repo_info.version, &asset.name)
+        .is_some()
+    {
+        status!("Would use cached {}", asset.name);
+    } else if asset.size > 0 {
+        status!(
+            "Would download {} ({:.2} MB)",
+            asset.name,
+            asset.size as f32 / 1000000_f32
+        );
+    } else {
+        status!("Would download {}", asset.name);
+    }
 
-    if tar_infos.len() > 1 {
-        println!("Multiple files found in archive. Aborting!");
-        return Ok(());
+    let binary = bin_name.or(previous_package
+        .as_ref()
+        .map(|package| package.binary.as_str()));
+    match binary {
+        Some(binary) => {
+            let binary_path = install_path.join(binary);
+            database.ensure_unclaimed(&binary_path, &key)?;
+            if binary_path.exists() {
+                status!("Would overwrite {}", binary_path.display());
+            } else {
+                status!("Would install {}", binary_path.display());
+            }
+        }
+        None => status!(
+            "Would install the binary of {} to {}",
+            asset.name,
+            install_path.display()
+        ),
+    }
+
+    match previous_package {
+        Some(package) => status!(
+            "Would update the record of {} from {} to {}",
+            repo_info.full_name,
+            package.version,
+            repo_info.version
+        ),
+        None => status!(
+            "Would add a record for {} {}",
+            repo_info.full_name,
+            repo_info.version
+        ),
     }
+    Ok(())
+}
+
+fn install_asset(
+    database: &Database,
+    repo_info: &RepoInfo,
+    name: &str,
+    asset: &AssetInfo,
+    download_path: &Path,
+    install_path: &Path,
+    extract: &ExtractOptions,
+) -> Result<(), Error> {
+    // Removed with everything in it on return, also when the installation fails
+    let staging_dir = staging::create_staging_dir(install_path)?;
+    let tmp_decompress_file = NamedTempFile::new_in(staging_dir.path())?;
+
+    status!("Decompressing {}...", asset.name);
+    archive::decompress_file(download_path, tmp_decompress_file.path())?;
+
+    status!("Unpacking {}...", asset.name);
+    let tmp_unpack_dir = tempfile::tempdir_in(staging_dir.path())?;
+    let unpacked_files = archive::unpacking_archive(
+        tmp_decompress_file.path(),
+        tmp_unpack_dir.path(),
+        extract.strip_components,
+    )?;
+
+    // The binary may be renamed, so it is also looked up by the name it had in the archive before
+    let source_name = extract
+        .source
+        .as_deref()
+        .and_then(|source| Path::new(source).file_name())
+        .and_then(OsStr::to_str);
+    let names: Vec<&str> = extract
+        .bin_name
+        .as_deref()
+        .into_iter()
+        .chain(source_name)
+        .collect();
+    let unpacked_file = archive::select_binary(&unpacked_files, &names)?;
+    let binary = match &extract.bin_name {
+        Some(bin_name) => bin_name.to_string(),
+        None => unpacked_file
+            .path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+    };
 
-    println!(
+    status!(
         "Installing {} to {}",
-        tar_infos[0].name,
-        install_path.to_str().unwrap()
+        unpacked_file.source,
+        install_path.join(&binary).display()
     );
-    archive::unpacking_archive(tmp_decompress_file.path(), install_path);
+    let key = database::key(&repo_info.full_name);
+    let binary_path = install_path.join(&binary);
+    database.ensure_unclaimed(&binary_path, &key)?;
+
+    let mut transaction = FileTransaction::default();
+    let mut checksums = BTreeMap::new();
+    let staged_binary = tmp_unpack_dir.path().join(&unpacked_file.path);
+    checksums.insert(
+        binary_path.to_string_lossy().to_string(),
+        hash::sha256_file(&staged_binary)?,
+    );
+    transaction.place(&staged_binary, &binary_path)?;
+
+    let mut files = Vec::new();
+    for (extra_file, destination) in
+        extras::find(&unpacked_files, unpacked_file, &Config::get_data_path())
+    {
+        if let Err(error) = database.ensure_unclaimed(&destination, &key) {
+            eprintln!("Skipping {}: {}", extra_file.source, error);
+            continue;
+        }
+        status!(
+            "Installing {} to {}",
+            extra_file.source,
+            destination.display()
+        );
+        let staged_file = tmp_unpack_dir.path().join(&extra_file.path);
+        let destination = destination.to_string_lossy().to_string();
+        checksums.insert(destination.clone(), hash::sha256_file(&staged_file)?);
+        transaction.place(&staged_file, Path::new(&destination))?;
+        files.push(destination);
+    }
 
     let package = Package {
-        name: repo_info.name,
-        full_name: repo_info.full_name,
-        version: repo_info.version,
+        name: name.to_string(),
+        full_name: repo_info.full_name.to_string(),
+        version: repo_info.version.to_string(),
         path: install_path.to_string_lossy().to_string(),
-        binary: tar_infos[0].name.to_string(),
+        binary,
+        asset: Some(asset.name.to_string()),
+        url: Some(asset.url.to_string()),
+        sha256: Some(hash::sha256_file(download_path)?),
+        source: Some(unpacked_file.source.to_string()),
+        strip_components: extract.strip_components,
+        files,
+        checksums,
     };
 
-    database.put(&package.name, &package).unwrap();
-    println!("Done!");
+    // The transaction is rolled back when it is dropped on an error
+    let previous_package = database.get(&key)?;
+    database.put(&key, &package)?;
+    transaction.commit();
+
+    if let Some(warning) = search_path::check_reachable(install_path, &package.binary) {
+        eprintln!("{}", warning);
+    }
+
+    // Files of the previous version which the new one does not ship anymore
+    if let Some(previous_package) = previous_package {
+        for file in previous_package
+            .files
+            .iter()
+            .filter(|file| !package.files.contains(file))
+        {
+            remove_tracked_file(Path::new(file));
+        }
+    }
+
+    let download_cache = DownloadCache::new(Config::get_download_cache_path());
+    if let Err(error) = download_cache.prune(&package.full_name, &package.version, KEPT_RELEASES) {
+        eprintln!(
+            "Could not clean up the cached downloads of {}: {}",
+            package.full_name, error
+        );
+    }
     Ok(())
 }
 
-fn uninstall(database: &Database, package_name: &str) -> Result<(), Box<dyn Error>> {
-    let package = database.get(package_name).unwrap();
-    if package.is_some() {
-        let package = package.unwrap();
-        let path = PathBuf::from(package.path).join(package.binary);
-        fs::remove_file(path)?;
-        database.remove(package_name)?;
+fn export(database: &Database, config: &Config, lockfile_path: &Path) -> Result<(), Error> {
+    let packages = database.get_all()?;
+    let (lockfile, skipped_packages) = Lockfile::from_packages(&packages, config);
+    for package in skipped_packages {
+        eprintln!(
+            "Skipping {}, its asset is unknown. Reinstall it to include it",
+            package.name
+        );
     }
+    lockfile.save(lockfile_path)?;
+    status!(
+        "Exported {} packages to {}",
+        lockfile.packages.len(),
+        lockfile_path.display()
+    );
     Ok(())
 }
+
+fn import(
+    database: &Database,
+    config: &Config,
+    lockfile_path: &Path,
+    dry_run: bool,
+    offline: bool,
+) -> Result<(), Error> {
+    let lockfile = Lockfile::load(lockfile_path)?;
+    let installed_packages = database.get_all()?;
+
+    let mut pending_packages: Vec<&LockedPackage> = Vec::new();
+    for locked_package in &lockfile.packages {
+        let installed = installed_packages.iter().any(|package| {
+            package.full_name.eq_ignore_ascii_case(&locked_package.repo)
+                && package.sha256.as_ref() == Some(&locked_package.sha256)
+        });
+        if installed {
+            status!(
+                "{} {} is already installed",
+                locked_package.repo,
+                locked_package.tag
+            );
+        } else {
+            pending_packages.push(locked_package);
+        }
+    }
+
+    if pending_packages.is_empty() {
+        status!("Nothing to import");
+        return Ok(());
+    }
+
+    let mut downloads = Vec::new();
+    for locked_package in &pending_packages {
+        let install_path = locked_package.install_path(config)?;
+        privilege::ensure_placeable(&install_path)?;
+        let asset = AssetInfo {
+            name: locked_package.asset.to_string(),
+            url: locked_package.url.to_string(),
+            size: 0,
+            download_count: 0,
+        };
+        if dry_run {
+            describe_install(
+                database,
+                &locked_package.repo_info(),
+                &asset,
+                &install_path,
+                Some(&locked_package.binary),
+            )?;
+            continue;
+        }
+        downloads.push((*locked_package, asset, install_path, NamedTempFile::new()?));
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    status!("Downloading {} assets...", downloads.len());
+    let download_cache = DownloadCache::new(Config::get_download_cache_path());
+    let download_results = parallel::map(
+        &downloads,
+        config.jobs,
+        |(locked_package, asset, _, tmp_download_file)| {
+            download_cache.fetch(
+                &locked_package.repo,
+                &locked_package.tag,
+                asset,
+                Some(&locked_package.sha256),
+                tmp_download_file.path(),
+                offline,
+            )
+        },
+    );
+
+    // Verify everything before installing anything, so a tampered asset leaves the system untouched
+    for ((locked_package, asset, _, tmp_download_file), download_result) in
+        downloads.iter().zip(download_results)
+    {
+        download_result?;
+        hash::verify_sha256(
+            tmp_download_file.path(),
+            &asset.name,
+            &locked_package.sha256,
+        )?;
+    }
+
+    for (locked_package, asset, install_path, tmp_download_file) in &downloads {
+        status!(
+            "Installing {} {}...",
+            locked_package.repo,
+            locked_package.tag
+        );
+        install_asset(
+            database,
+            &locked_package.repo_info(),
+            &locked_package.name,
+            asset,
+            tmp_download_file.path(),
+            install_path,
+            &ExtractOptions {
+                strip_components: locked_package.strip_components,
+                bin_name: Some(locked_package.binary.to_string()),
+                source: locked_package.source.clone(),
+            },
+        )?;
+    }
+
+    status!("Done!");
+    Ok(())
+}
+
+/// Reinstalls the newest cached release which is older than the installed one, or the given tag.
+fn rollback(
+    database: &Database,
+    package_name: &str,
+    tag: Option<&str>,
+    interactive: bool,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let package = database
+        .find(package_name)?
+        .ok_or_else(|| database::Error::NotInstalled(vec![package_name.to_string()]))?;
+    let cache = DownloadCache::new(Config::get_download_cache_path());
+    let release = match tag {
+        Some(tag) => cache
+            .releases(&package.full_name)
+            .into_iter()
+            .find(|(cached_tag, _)| cached_tag == tag),
+        None => cache.previous_release(&package.full_name, &package.version),
+    };
+    let Some((tag, asset_names)) = release else {
+        let resource = match tag {
+            Some(tag) => format!("Release {} of {}", tag, package.full_name),
+            None => format!(
+                "A release of {} before {}",
+                package.full_name, package.version
+            ),
+        };
+        return Err(repo::Error::NotCached(resource).into());
+    };
+
+    let assets: Vec<AssetInfo> = asset_names
+        .into_iter()
+        .map(|name| AssetInfo {
+            url: format!(
+                "https://github.com/{}/releases/download/{}/{}",
+                package.full_name, tag, name
+            ),
+            size: cache
+                .get(&package.full_name, &tag, &name)
+                .and_then(|path| fs::metadata(path).ok())
+                .map_or(0, |metadata| metadata.len() as i64),
+            name,
+            download_count: 0,
+        })
+        .collect();
+    // The asset of the installed release usually differs only by the version in its name
+    let asset_index = match assets
+        .iter()
+        .position(|asset| package.asset.as_ref() == Some(&asset.name))
+    {
+        Some(index) => index,
+        None => selection::select_asset(&assets, &AssetSelector::Any, interactive)?,
+    };
+    let asset = &assets[asset_index];
+    let repo_info = RepoInfo {
+        name: package.name.to_string(),
+        full_name: package.full_name.to_string(),
+        description: None,
+        version: tag.to_string(),
+        published_at: None,
+        assets: Vec::new(),
+    };
+
+    let install_path = Path::new(&package.path);
+    privilege::ensure_placeable(install_path)?;
+    status!(
+        "Rolling back {} from {} to {}...",
+        package.full_name,
+        package.version,
+        tag
+    );
+    if dry_run {
+        return describe_install(
+            database,
+            &repo_info,
+            asset,
+            install_path,
+            Some(&package.binary),
+        );
+    }
+
+    let download_path = cache
+        .get(&package.full_name, &tag, &asset.name)
+        .ok_or_else(|| repo::Error::NotCached(format!("Asset {}", asset.name)))?;
+    install_asset(
+        database,
+        &repo_info,
+        &package.name,
+        asset,
+        &download_path,
+        install_path,
+        &ExtractOptions {
+            strip_components: package.strip_components,
+            bin_name: Some(package.binary.to_string()),
+            source: package.source.clone(),
+        },
+    )?;
+    status!("Done!");
+    Ok(())
+}
+
+/// Uninstalls all known packages and fails afterwards if some were not installed.
+fn uninstall(
+    database: &Database,
+    package_names: &[&str],
+    options: &UninstallOptions,
+) -> Result<(), Error> {
+    let mut packages: Vec<Package> = Vec::new();
+    let mut unknown_names = Vec::new();
+    for package_name in package_names {
+        match database.find(package_name)? {
+            Some(package) if !packages.contains(&package) => packages.push(package),
+            Some(_) => {}
+            None => unknown_names.push(package_name.to_string()),
+        }
+    }
+
+    // Scripts without a terminal are not asked
+    if !packages.is_empty() && !options.dry_run && options.interactive && io::stdin().is_terminal()
+    {
+        let full_names: Vec<&str> = packages
+            .iter()
+            .map(|package| package.full_name.as_str())
+            .collect();
+        prompt::confirm(&format!("Uninstall {}?", full_names.join(", ")))?;
+    }
+
+    let http_cache = HttpCache::new(Config::get_http_cache_path());
+    let download_cache = DownloadCache::new(Config::get_download_cache_path());
+    for package in &packages {
+        remove_package(database, package, options.dry_run)?;
+        if options.purge {
+            let mut paths = http_cache.remove_repo(&package.full_name, options.dry_run)?;
+            paths.extend(download_cache.remove_repo(&package.full_name, options.dry_run)?);
+            for path in paths {
+                if options.dry_run {
+                    status!("Would remove {}", path.display());
+                }
+            }
+        }
+    }
+
+    if !unknown_names.is_empty() {
+        return Err(database::Error::NotInstalled(unknown_names).into());
+    }
+    Ok(())
+}
+
+/// Removes the files of a package and its record. Files which are already gone are reported,
+/// the record is removed anyway.
+fn remove_package(database: &Database, package: &Package, dry_run: bool) -> Result<(), Error> {
+    let binary_path = PathBuf::from(&package.path).join(&package.binary);
+    let paths = std::iter::once(binary_path.clone()).chain(package.files.iter().map(PathBuf::from));
+    for path in paths {
+        if dry_run {
+            status!("Would remove {}", path.display());
+        } else if !path.exists() && !path.is_symlink() {
+            eprintln!("Warning: {} was already removed", path.display());
+        } else if path == binary_path {
+            // The record is kept if the binary can not be removed
+            privilege::remove_file(&path)?;
+        } else {
+            remove_tracked_file(&path);
+        }
+    }
+
+    if dry_run {
+        status!("Would forget {} {}", package.full_name, package.version);
+    } else {
+        database.remove(&package.key())?;
+        status!("Uninstalled {} {}", package.full_name, package.version);
+    }
+    Ok(())
+}
+
+/// Removes a completion or man page, a file which is already gone is no error.
+fn remove_tracked_file(path: &Path) {
+    if !path.exists() {
+        return;
+    }
+    if let Err(error) = privilege::remove_file(path) {
+        eprintln!("Could not remove {}: {}", path.display(), error);
+    }
+}
@@ -0,0 +1,149 @@
+use serde::Deserialize;
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::database;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            Error::Parse(path, error) => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// List of packages which should be installed, e.g.:
+///
+/// ```toml
+/// [[package]]
+/// repo = "zellij-org/zellij"
+/// tag = "v0.34.4"
+/// asset = "*x86_64*linux-musl.tar.gz"
+/// binary = "zellij"
+/// strip_components = 1
+/// prefix = "work"
+/// ```
+#[derive(Deserialize, PartialEq, Debug)]
+pub struct Manifest {
+    #[serde(default, rename = "package")]
+    pub packages: Vec<ManifestPackage>,
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestPackage {
+    pub repo: String,
+    pub tag: Option<String>,
+    pub asset: Option<String>,
+    #[serde(default, deserialize_with = "database::deserialize_optional_name")]
+    pub binary: Option<String>,
+    /// Name the package is installed as, like `grpm install --as`.
+    #[serde(default, deserialize_with = "database::deserialize_optional_name")]
+    pub alias: Option<String>,
+    pub strip_components: Option<usize>,
+    pub prefix: Option<String>,
+    pub install_path: Option<String>,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Manifest, Error> {
+        let content =
+            fs::read_to_string(path).map_err(|error| Error::Io(path.to_path_buf(), error))?;
+        toml::from_str(&content).map_err(|error| Error::Parse(path.to_path_buf(), error))
+    }
+
+    pub fn contains(&self, full_name: &str) -> bool {
+        self.packages
+            .iter()
+            .any(|package| package.repo.eq_ignore_ascii_case(full_name))
+    }
+}
+
+impl ManifestPackage {
+    pub fn install_path(&self) -> Option<PathBuf> {
+        self.install_path
+            .as_ref()
+            .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            [[package]]
+            repo = "zellij-org/zellij"
+            tag = "v0.34.4"
+            asset = "*x86_64*linux-musl.tar.gz"
+            binary = "zellij"
+            strip_components = 1
+            install_path = "/opt/bin"
+
+            [[package]]
+            repo = "sharkdp/bat"
+            alias = "batcat"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(manifest.packages.len(), 2);
+        assert_eq!(manifest.packages[0].tag.as_deref(), Some("v0.34.4"));
+        assert_eq!(manifest.packages[0].strip_components, Some(1));
+        assert_eq!(
+            manifest.packages[0].install_path(),
+            Some(PathBuf::from("/opt/bin"))
+        );
+        assert!(manifest.packages[1].tag.is_none());
+        assert_eq!(manifest.packages[1].alias.as_deref(), Some("batcat"));
+        assert!(manifest.packages[1].install_path().is_none());
+        assert!(manifest.contains("SharkDP/bat"));
+        assert!(!manifest.contains("sharkdp/fd"));
+    }
+
+    #[test]
+    fn parse_empty() {
+        let manifest: Manifest = toml::from_str("").unwrap();
+        assert!(manifest.packages.is_empty());
+    }
+
+    #[test]
+    fn parse_unknown_field() {
+        let result: Result<Manifest, _> = toml::from_str(
+            r#"
+            [[package]]
+            repo = "sharkdp/bat"
+            version = "v0.22.1"
+            "#,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parse_invalid_names() {
+        for field in [
+            "binary = \"../../.bashrc\"",
+            "alias = \"bin/bat\"",
+            "alias = \"..\"",
+        ] {
+            let result: Result<Manifest, _> =
+                toml::from_str(&format!("[[package]]\nrepo = \"sharkdp/bat\"\n{}\n", field));
+            assert!(result.is_err(), "{} was accepted", field);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::env::consts;
+
+/// Suffixes of release assets which are not installable, like checksums and signatures.
+const IGNORED_SUFFIXES: [&str; 8] = [
+    ".sha256",
+    ".sha256sum",
+    ".sha512",
+    ".md5",
+    ".sig",
+    ".asc",
+    ".pem",
+    ".txt",
+];
+
+fn os_keywords(os: &str) -> &'static [&'static str] {
+    match os {
+        "linux" => &["linux"],
+        "macos" => &["darwin", "macos", "apple", "osx"],
+        "windows" => &["windows", "win64", "win32"],
+        "freebsd" => &["freebsd"],
+        _ => &[],
+    }
+}
+
+fn arch_keywords(arch: &str) -> &'static [&'static str] {
+    match arch {
+        "x86_64" => &["x86_64", "x86-64", "amd64", "x64"],
+        "x86" => &["i686", "i386", "x86"],
+        "aarch64" => &["aarch64", "arm64"],
+        "arm" => &["armv7", "armhf", "arm"],
+        _ => &[],
+    }
+}
+
+/// Returns true if the asset looks like a build for the given os and architecture.
+pub fn matches_platform(asset_name: &str, os: &str, arch: &str) -> bool {
+    let name = asset_name.to_lowercase();
+    if IGNORED_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) {
+        return false;
+    }
+    let os_matches = os_keywords(os).iter().any(|keyword| name.contains(keyword));
+    let arch_matches = arch_keywords(arch)
+        .iter()
+        .any(|keyword| name.contains(keyword));
+    os_matches && arch_matches
+}
+
+/// Returns true if the asset looks like a build for this machine.
+pub fn matches_host(asset_name: &str) -> bool {
+    matches_platform(asset_name, consts::OS, consts::ARCH)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn linux_x86_64() {
+        assert!(matches_platform(
+            "zellij-x86_64-unknown-linux-musl.tar.gz",
+            "linux",
+            "x86_64"
+        ));
+        assert!(matches_platform(
+            "fd_8.6.0_amd64_linux.tar.gz",
+            "linux",
+            "x86_64"
+        ));
+        assert!(!matches_platform(
+            "zellij-aarch64-unknown-linux-musl.tar.gz",
+            "linux",
+            "x86_64"
+        ));
+        assert!(!matches_platform(
+            "zellij-x86_64-apple-darwin.tar.gz",
+            "linux",
+            "x86_64"
+        ));
+    }
+
+    #[test]
+    fn macos_aarch64() {
+        assert!(matches_platform(
+            "zellij-aarch64-apple-darwin.tar.gz",
+            "macos",
+            "aarch64"
+        ));
+        assert!(matches_platform(
+            "tool_macOS_arm64.tar.gz",
+            "macos",
+            "aarch64"
+        ));
+    }
+
+    #[test]
+    fn ignores_checksums() {
+        assert!(!matches_platform(
+            "zellij-x86_64-unknown-linux-musl.sha256sum",
+            "linux",
+            "x86_64"
+        ));
+    }
+
+    #[test]
+    fn unknown_platform() {
+        assert!(!matches_platform(
+            "tool-linux-x86_64.tar.gz",
+            "haiku",
+            "x86_64"
+        ));
+    }
+}
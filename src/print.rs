@@ -1,68 +1,376 @@
 use prettytable::{format, Cell, Row, Table};
+use serde::Serialize;
+use std::{
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+    sync::OnceLock,
+};
 
 use crate::{
-    archive::TarInfo,
+    adopt::Suggestion,
     database::Package,
-    repo::{AssetInfo, RepoInfo},
+    doctor::{Finding, Severity},
+    repo::{AssetInfo, RepoInfo, SearchResult},
+    verify::{self, FileReport},
+    version,
 };
 
+static OUTPUT_FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Writing a result to stdout failed.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Yaml(serde_yaml::Error),
+    Csv(csv::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Json(error) => write!(f, "{}", error),
+            Error::Yaml(error) => write!(f, "{}", error),
+            Error::Csv(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Json(error)
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(error: serde_yaml::Error) -> Self {
+        Error::Yaml(error)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(error: csv::Error) -> Self {
+        Error::Csv(error)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Yaml,
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "yaml" => Ok(OutputFormat::Yaml),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("Unknown output format '{}'", format)),
+        }
+    }
+}
+
+/// Row of the update candidates in machine readable output.
+#[derive(Serialize)]
+struct UpdateRow<'a> {
+    name: &'a str,
+    full_name: &'a str,
+    current_version: &'a str,
+    available_version: &'a str,
+    delta: String,
+    published_at: Option<&'a str>,
+}
+
+/// Row of the configuration in machine readable output.
+#[derive(Serialize)]
+struct ConfigRow<'a> {
+    key: &'a str,
+    value: &'a str,
+}
+
+/// Row of the assets of a repository in CSV output.
+#[derive(Serialize)]
+struct AssetRow<'a> {
+    full_name: &'a str,
+    version: &'a str,
+    name: &'a str,
+    url: &'a str,
+    size: i64,
+    download_count: i64,
+}
+
+/// Flat package for CSV, which can not hold the list of files.
+#[derive(Serialize)]
+struct PackageRow<'a> {
+    name: &'a str,
+    full_name: &'a str,
+    version: &'a str,
+    binary: &'a str,
+    path: &'a str,
+    asset: Option<&'a str>,
+    url: Option<&'a str>,
+    sha256: Option<&'a str>,
+    source: Option<&'a str>,
+    strip_components: usize,
+    files: String,
+}
+
+impl<'a> From<&'a Package> for PackageRow<'a> {
+    fn from(package: &'a Package) -> Self {
+        PackageRow {
+            name: &package.name,
+            full_name: &package.full_name,
+            version: &package.version,
+            binary: &package.binary,
+            path: &package.path,
+            asset: package.asset.as_deref(),
+            url: package.url.as_deref(),
+            sha256: package.sha256.as_deref(),
+            source: package.source.as_deref(),
+            strip_components: package.strip_components,
+            files: package.files.join(";"),
+        }
+    }
+}
+
+/// Prints progress and informational messages.
+/// They are written to stderr if stdout is reserved for machine readable output.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::print::is_machine_readable() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+pub(crate) use status;
+
+pub fn set_output_format(output_format: OutputFormat) {
+    OUTPUT_FORMAT.set(output_format).unwrap();
+}
+
+pub fn output_format() -> OutputFormat {
+    *OUTPUT_FORMAT.get().unwrap_or(&OutputFormat::Table)
+}
+
+pub fn is_machine_readable() -> bool {
+    output_format() != OutputFormat::Table
+}
+
+/// Prints a question without a newline, on stderr if stdout is reserved for machine readable output.
+pub fn print_question(question: &str) -> io::Result<()> {
+    if is_machine_readable() {
+        eprint!("{}: ", question);
+        io::stderr().flush()
+    } else {
+        print!("{}: ", question);
+        io::stdout().flush()
+    }
+}
+
 pub fn print_repo_info(repo: &RepoInfo) {
-    let repo_table = create_repo_table(&repo);
-    let asset_table = create_asset_table(&repo.assets);
+    let repo_table = create_repo_table(repo);
+    print_decoration(&repo_table);
+}
 
-    repo_table.print_tty(true).unwrap();
-    println!("");
-    asset_table.print_tty(true).unwrap();
-    println!("");
+pub fn print_assets(assets: &[(usize, &AssetInfo)]) {
+    let asset_table = create_asset_table(assets);
+    print_decoration(&asset_table);
 }
 
-pub fn print_packages(packages: &Vec<Package>) {
-    let table = create_packages_table(&packages);
-    table.print_tty(true).unwrap();
+pub fn print_repo(repo: &RepoInfo) -> Result<(), Error> {
+    match output_format() {
+        OutputFormat::Table => {
+            let assets: Vec<(usize, &AssetInfo)> = repo.assets.iter().enumerate().collect();
+            print_repo_info(repo);
+            print_assets(&assets);
+            Ok(())
+        }
+        OutputFormat::Csv => {
+            let rows: Vec<AssetRow> = repo
+                .assets
+                .iter()
+                .map(|asset| AssetRow {
+                    full_name: &repo.full_name,
+                    version: &repo.version,
+                    name: &asset.name,
+                    url: &asset.url,
+                    size: asset.size,
+                    download_count: asset.download_count,
+                })
+                .collect();
+            print_csv(&rows)
+        }
+        _ => print_serialized(repo),
+    }
 }
 
-pub fn _print_binaries(tar_infos: &Vec<TarInfo>) {
-    let mut table = Table::new();
-    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-    let headers = Row::new(vec![
-        Cell::new("#").style_spec("b"),
-        Cell::new("Name").style_spec("b"),
-        Cell::new("Size (MB)").style_spec("br"),
-    ]);
-    table.set_titles(headers);
+pub fn print_search_results(results: &[SearchResult]) -> Result<(), Error> {
+    match output_format() {
+        OutputFormat::Table => {
+            let rows: Vec<(usize, &SearchResult)> = results.iter().enumerate().collect();
+            let table = create_search_table(&rows);
+            table.print_tty(true)?;
+            Ok(())
+        }
+        OutputFormat::Csv => print_csv(results),
+        _ => print_serialized(results),
+    }
+}
 
-    for (i, tar_info) in tar_infos.iter().enumerate() {
-        let index = &i.to_string();
-        let name = &tar_info.name;
-        let size = &format!("{:.2}", tar_info.size as f32 / 1000000 as f32);
-        let tar_row = Row::new(vec![
-            Cell::new(index),
-            Cell::new(name),
-            Cell::new(size).style_spec("r"),
-        ]);
-        table.add_row(tar_row);
+pub fn print_search_choices(results: &[(usize, &SearchResult)]) {
+    let table = create_search_table(results);
+    print_decoration(&table);
+}
+
+pub fn print_packages(packages: &[Package]) -> Result<(), Error> {
+    match output_format() {
+        OutputFormat::Table => {
+            let table = create_packages_table(packages);
+            table.print_tty(true)?;
+            Ok(())
+        }
+        OutputFormat::Csv => {
+            let rows: Vec<PackageRow> = packages.iter().map(PackageRow::from).collect();
+            print_csv(&rows)
+        }
+        _ => print_serialized(&packages),
+    }
+}
+
+pub fn print_config(entries: &[(String, String)]) -> Result<(), Error> {
+    if output_format() == OutputFormat::Table {
+        let table = create_config_table(entries);
+        table.print_tty(true)?;
+        return Ok(());
+    }
+
+    let rows: Vec<ConfigRow> = entries
+        .iter()
+        .map(|(key, value)| ConfigRow { key, value })
+        .collect();
+    match output_format() {
+        OutputFormat::Csv => print_csv(&rows),
+        _ => print_serialized(&rows),
+    }
+}
+
+pub fn print_findings(findings: &[Finding]) -> Result<(), Error> {
+    match output_format() {
+        OutputFormat::Table => {
+            let table = create_findings_table(findings);
+            table.print_tty(true)?;
+            Ok(())
+        }
+        OutputFormat::Csv => print_csv(findings),
+        _ => print_serialized(findings),
+    }
+}
+
+pub fn print_file_reports(reports: &[FileReport]) -> Result<(), Error> {
+    match output_format() {
+        OutputFormat::Table => {
+            let table = create_file_reports_table(reports);
+            table.print_tty(true)?;
+            Ok(())
+        }
+        OutputFormat::Csv => print_csv(reports),
+        _ => print_serialized(reports),
+    }
+}
+
+pub fn print_suggestions(suggestions: &[Suggestion]) -> Result<(), Error> {
+    match output_format() {
+        OutputFormat::Table => {
+            let table = create_suggestions_table(suggestions);
+            table.print_tty(true)?;
+            Ok(())
+        }
+        OutputFormat::Csv => print_csv(suggestions),
+        _ => print_serialized(suggestions),
+    }
+}
+
+pub fn print_updates(updateable_packages: &[(Package, RepoInfo)]) -> Result<(), Error> {
+    if output_format() == OutputFormat::Table {
+        let table = create_update_table(updateable_packages);
+        table.print_tty(true)?;
+        return Ok(());
     }
 
-    table.print_tty(true).unwrap();
-    println!("");
+    let rows: Vec<UpdateRow> = updateable_packages
+        .iter()
+        .map(|(package, repo_info)| UpdateRow {
+            name: &package.name,
+            full_name: &package.full_name,
+            current_version: &package.version,
+            available_version: &repo_info.version,
+            delta: version::delta(&package.version, &repo_info.version).to_string(),
+            published_at: repo_info.published_at.as_deref(),
+        })
+        .collect();
+    match output_format() {
+        OutputFormat::Csv => print_csv(&rows),
+        _ => print_serialized(&rows),
+    }
 }
 
-pub fn print_updates(updateable_packages: &Vec<(Package, RepoInfo)>) {
-    let table = create_update_table(updateable_packages);
-    table.print_tty(true).unwrap();
+/// Prints tables which are not part of the result, on stderr in machine readable output.
+fn print_decoration(table: &Table) {
+    if is_machine_readable() {
+        table.print(&mut io::stderr()).unwrap();
+        eprintln!();
+    } else {
+        table.print_tty(true).unwrap();
+        println!();
+    }
 }
 
-pub fn print_index_question(question: &str) -> usize {
-    print!("{}: ", question);
-    text_io::read!()
+fn print_serialized<T: Serialize + ?Sized>(value: &T) -> Result<(), Error> {
+    let mut stdout = io::stdout().lock();
+    match output_format() {
+        OutputFormat::Yaml => serde_yaml::to_writer(&mut stdout, value)?,
+        _ => {
+            serde_json::to_writer_pretty(&mut stdout, value)?;
+            writeln!(stdout)?;
+        }
+    }
+    Ok(())
+}
+
+fn print_csv<T: Serialize>(rows: &[T]) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(io::stdout().lock());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
 }
 
-fn create_packages_table(packages: &Vec<Package>) -> Table {
+fn create_packages_table(packages: &[Package]) -> Table {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
 
     let headers = Row::new(vec![
         Cell::new("Package"),
+        Cell::new("Repository"),
         Cell::new("Version"),
         Cell::new("Path"),
     ]);
@@ -72,6 +380,7 @@ fn create_packages_table(packages: &Vec<Package>) -> Table {
     for package in packages {
         let package_row = Row::new(vec![
             Cell::new(&package.name),
+            Cell::new(&package.full_name),
             Cell::new(&package.version),
             Cell::new(&package.path),
         ]);
@@ -81,6 +390,99 @@ fn create_packages_table(packages: &Vec<Package>) -> Table {
     table
 }
 
+fn create_config_table(entries: &[(String, String)]) -> Table {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(vec![Cell::new("Key"), Cell::new("Value")]));
+
+    for (key, value) in entries {
+        table.add_row(Row::new(vec![Cell::new(key), Cell::new(value)]));
+    }
+
+    table
+}
+
+fn create_findings_table(findings: &[Finding]) -> Table {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(vec![
+        Cell::new("Check"),
+        Cell::new("Status"),
+        Cell::new("Details"),
+        Cell::new("Suggestion"),
+    ]));
+
+    for finding in findings {
+        let style = match finding.severity {
+            Severity::Ok | Severity::Fixed => "Fg",
+            Severity::Warning => "Fy",
+            Severity::Problem => "Fr",
+        };
+        table.add_row(Row::new(vec![
+            Cell::new(&finding.check),
+            Cell::new(&finding.severity.to_string()).style_spec(style),
+            Cell::new(&finding.message),
+            Cell::new(finding.suggestion.as_deref().unwrap_or_default()),
+        ]));
+    }
+
+    table
+}
+
+fn create_file_reports_table(reports: &[FileReport]) -> Table {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(vec![
+        Cell::new("Package"),
+        Cell::new("File"),
+        Cell::new("Status"),
+        Cell::new("Details"),
+    ]));
+
+    for report in reports {
+        let style = match report.status {
+            verify::Status::Ok => "Fg",
+            verify::Status::Unverified => "Fy",
+            _ => "Fr",
+        };
+        table.add_row(Row::new(vec![
+            Cell::new(&report.package),
+            Cell::new(&report.path),
+            Cell::new(&report.status.to_string()).style_spec(style),
+            Cell::new(report.details.as_deref().unwrap_or_default()),
+        ]));
+    }
+
+    table
+}
+
+fn create_suggestions_table(suggestions: &[Suggestion]) -> Table {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(vec![
+        Cell::new("Binary"),
+        Cell::new("Repository"),
+        Cell::new("Command"),
+    ]));
+
+    for suggestion in suggestions {
+        let (repository, command) = match &suggestion.repository {
+            Some(repository) => (
+                repository.as_str(),
+                format!("grpm adopt {} --binary {}", repository, suggestion.binary),
+            ),
+            None => ("", String::new()),
+        };
+        table.add_row(Row::new(vec![
+            Cell::new(&suggestion.binary),
+            Cell::new(repository),
+            Cell::new(&command),
+        ]));
+    }
+
+    table
+}
+
 fn create_repo_table(repo: &RepoInfo) -> Table {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_CLEAN);
@@ -104,7 +506,7 @@ fn create_repo_table(repo: &RepoInfo) -> Table {
     if let Some(description) = description {
         let description_row = Row::new(vec![
             Cell::new("Description:"),
-            Cell::new(&description).style_spec("b"),
+            Cell::new(description).style_spec("b"),
         ]);
         table.add_row(description_row);
     }
@@ -112,7 +514,7 @@ fn create_repo_table(repo: &RepoInfo) -> Table {
     table
 }
 
-fn create_asset_table(assets: &Vec<AssetInfo>) -> Table {
+fn create_asset_table(assets: &[(usize, &AssetInfo)]) -> Table {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
     let headers = Row::new(vec![
@@ -124,10 +526,10 @@ fn create_asset_table(assets: &Vec<AssetInfo>) -> Table {
 
     table.set_titles(headers);
 
-    for (i, asset) in assets.iter().enumerate() {
+    for (i, asset) in assets {
         let index = &i.to_string();
         let name = &asset.name;
-        let size = &format!("{:.2}", asset.size as f32 / 1000000 as f32);
+        let size = &format!("{:.2}", asset.size as f32 / 1000000_f32);
         let downloads = &asset.download_count.to_string();
         let asset_row = Row::new(vec![
             Cell::new(index),
@@ -141,7 +543,37 @@ fn create_asset_table(assets: &Vec<AssetInfo>) -> Table {
     table
 }
 
-fn create_update_table(updatable_packages: &Vec<(Package, RepoInfo)>) -> Table {
+fn create_search_table(results: &[(usize, &SearchResult)]) -> Table {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    let headers = Row::new(vec![
+        Cell::new("#").style_spec("b"),
+        Cell::new("Repository").style_spec("b"),
+        Cell::new("Stars").style_spec("br"),
+        Cell::new("Version").style_spec("b"),
+        Cell::new("Description").style_spec("b"),
+    ]);
+
+    table.set_titles(headers);
+
+    for (i, result) in results {
+        let index = &i.to_string();
+        let stars = &result.stars.to_string();
+        let description = result.description.as_deref().unwrap_or("");
+        let result_row = Row::new(vec![
+            Cell::new(index),
+            Cell::new(&result.full_name).style_spec("Fcb"),
+            Cell::new(stars).style_spec("r"),
+            Cell::new(&result.version).style_spec("Fg"),
+            Cell::new(description),
+        ]);
+        table.add_row(result_row);
+    }
+
+    table
+}
+
+fn create_update_table(updatable_packages: &[(Package, RepoInfo)]) -> Table {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
 
@@ -149,18 +581,50 @@ fn create_update_table(updatable_packages: &Vec<(Package, RepoInfo)>) -> Table {
         Cell::new("Package"),
         Cell::new("Current version"),
         Cell::new("Available version"),
+        Cell::new("Change"),
+        Cell::new("Released"),
     ]);
 
     table.set_titles(headers);
 
     for package in updatable_packages {
+        let delta = version::delta(&package.0.version, &package.1.version).to_string();
+        let released = package
+            .1
+            .published_at
+            .as_ref()
+            .map_or("", |date| date.get(..10).unwrap_or(date));
         let package_row = Row::new(vec![
             Cell::new(&package.0.name),
             Cell::new(&package.0.version),
             Cell::new(&package.1.version),
+            Cell::new(&delta),
+            Cell::new(released),
         ]);
         table.add_row(package_row);
     }
 
     table
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util;
+    use std::path::Path;
+
+    #[test]
+    fn package_csv_row() {
+        let package = Package {
+            source: Some("ripgrep-13.0.0/rg".to_string()),
+            strip_components: 1,
+            files: vec!["/a/_rg".to_string(), "/a/rg.1".to_string()],
+            ..test_util::package("rg", Path::new("/home/user/.local/bin"))
+        };
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(PackageRow::from(&package)).unwrap();
+        let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert!(output.ends_with(",1,/a/_rg;/a/rg.1\n"));
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    env, fmt, fs, io,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
+
+/// Tools used to run the file placement with elevated privileges, in order of preference.
+const ESCALATION_TOOLS: [&str; 2] = ["sudo", "doas"];
+
+/// Hidden subcommand running a file operation, re-executed through the escalation tool.
+pub const FILE_OPERATION_COMMAND: &str = "file-operation";
+pub const FILE_OPERATIONS: [&str; 6] = ["place", "copy", "link", "rename", "remove", "executable"];
+
+#[derive(Debug)]
+pub enum Error {
+    NotWritable(PathBuf),
+    NoEscalationTool(PathBuf),
+    Failed(ExitStatus),
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotWritable(path) => write!(
+                f,
+                "{} is not writable, run as root or grant write access to it",
+                path.display()
+            ),
+            Error::NoEscalationTool(path) => write!(
+                f,
+                "{} is not writable and neither sudo nor doas is available",
+                path.display()
+            ),
+            Error::Failed(status) => {
+                write!(f, "Privileged file operation failed with {}", status)
+            }
+            Error::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+/// Returns true if files can be created in the directory by the current user.
+/// Missing directories are writable if their closest existing ancestor is.
+pub fn is_writable(dir: &Path) -> bool {
+    match dir.ancestors().find(|ancestor| ancestor.exists()) {
+        Some(existing) => tempfile::tempfile_in(existing).is_ok(),
+        None => false,
+    }
+}
+
+/// Fails if files can neither be placed in the directory directly nor through an escalation tool.
+/// Checked before downloading anything.
+pub fn ensure_placeable(dir: &Path) -> Result<(), Error> {
+    if is_writable(dir) || find_escalation_tool().is_some() {
+        Ok(())
+    } else {
+        Err(Error::NoEscalationTool(dir.to_path_buf()))
+    }
+}
+
+/// Moves the staged file to `destination`, replacing an existing file atomically.
+/// Privileges are escalated if the destination directory is not writable.
+pub fn place_file(staged: &Path, destination: &Path) -> Result<(), Error> {
+    perform("place", &[staged, destination])
+}
+
+/// Creates a hard link of `source` at `destination`.
+pub fn link_file(source: &Path, destination: &Path) -> Result<(), Error> {
+    perform("link", &[source, destination])
+}
+
+/// Renames `source` to `destination`, replacing an existing file atomically.
+pub fn rename_file(source: &Path, destination: &Path) -> Result<(), Error> {
+    perform("rename", &[source, destination])
+}
+
+/// Removes the file, escalating privileges if its directory is not writable.
+pub fn remove_file(path: &Path) -> Result<(), Error> {
+    perform("remove", &[path])
+}
+
+/// Sets the executable bits of the file, escalating privileges if it belongs to another user.
+pub fn set_executable(path: &Path) -> Result<(), Error> {
+    let paths = [path.to_path_buf()];
+    match run_file_operation("executable", &paths) {
+        Err(error) if error.kind() == io::ErrorKind::PermissionDenied => {
+            run_escalated("executable", &paths)
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Runs a file operation with the privileges of the current process.
+/// Used directly and by the hidden subcommand.
+pub fn run_file_operation(operation: &str, paths: &[PathBuf]) -> io::Result<()> {
+    match (operation, paths) {
+        ("place", [staged, destination]) => {
+            fs::create_dir_all(parent(destination))?;
+            if fs::rename(staged, destination).is_ok() {
+                return Ok(());
+            }
+            // Staged on a different filesystem
+            run_file_operation("copy", paths)
+        }
+        ("copy", [source, destination]) => {
+            // Copied next to the destination first, so the replacement itself is a rename
+            fs::create_dir_all(parent(destination))?;
+            let tmp_file = tempfile::Builder::new()
+                .prefix(".grpm-")
+                .tempfile_in(parent(destination))?;
+            fs::copy(source, tmp_file.path())?;
+            tmp_file.persist(destination).map_err(|error| error.error)?;
+            Ok(())
+        }
+        ("link", [source, destination]) => {
+            if destination.exists() {
+                fs::remove_file(destination)?;
+            }
+            fs::hard_link(source, destination)
+        }
+        ("rename", [source, destination]) => fs::rename(source, destination),
+        ("remove", [path]) => fs::remove_file(path),
+        ("executable", [path]) => {
+            let mut permissions = fs::metadata(path)?.permissions();
+            permissions.set_mode(permissions.mode() | 0o111);
+            fs::set_permissions(path, permissions)
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid file operation {}", operation),
+        )),
+    }
+}
+
+fn perform(operation: &str, paths: &[&Path]) -> Result<(), Error> {
+    let paths: Vec<PathBuf> = paths.iter().map(|path| path.to_path_buf()).collect();
+    let destination = &paths[paths.len() - 1];
+    if is_writable(parent(destination)) {
+        run_file_operation(operation, &paths)?;
+        return Ok(());
+    }
+    // Staged files of the invoking user are copied, so root never installs a user owned file
+    let operation = if operation == "place" {
+        "copy"
+    } else {
+        operation
+    };
+    run_escalated(operation, &paths)
+}
+
+fn parent(path: &Path) -> &Path {
+    path.parent().unwrap_or_else(|| Path::new("/"))
+}
+
+fn find_escalation_tool() -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    ESCALATION_TOOLS.iter().find_map(|tool| {
+        env::split_paths(&paths)
+            .map(|dir| dir.join(tool))
+            .find(|path| path.is_file())
+    })
+}
+
+fn run_escalated(operation: &str, paths: &[PathBuf]) -> Result<(), Error> {
+    let destination = &paths[paths.len() - 1];
+    let tool = find_escalation_tool()
+        .ok_or_else(|| Error::NoEscalationTool(parent(destination).to_path_buf()))?;
+    let status = Command::new(tool)
+        .arg(env::current_exe()?)
+        .arg("--system")
+        .arg(FILE_OPERATION_COMMAND)
+        .arg(operation)
+        .args(paths)
+        .status()?;
+    if !status.success() {
+        return Err(Error::Failed(status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn writable() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(is_writable(dir.path()));
+        assert!(ensure_placeable(dir.path()).is_ok());
+        assert!(is_writable(&dir.path().join("missing")));
+        assert!(!is_writable(Path::new("/proc/grpm")));
+    }
+
+    #[test]
+    fn place_and_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let staged = dir.path().join("staged");
+        let destination = dir.path().join("bin").join("tool");
+        fs::write(&staged, "binary").unwrap();
+
+        place_file(&staged, &destination).unwrap();
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "binary");
+        assert!(!staged.exists());
+
+        remove_file(&destination).unwrap();
+        assert!(!destination.exists());
+    }
+
+    #[test]
+    fn place_replaces() {
+        let dir = tempfile::tempdir().unwrap();
+        let staged = dir.path().join("staged");
+        let destination = dir.path().join("tool");
+        fs::write(&staged, "new").unwrap();
+        fs::write(&destination, "old").unwrap();
+
+        place_file(&staged, &destination).unwrap();
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "new");
+    }
+
+    #[test]
+    fn link_and_rename() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("tool");
+        let link = dir.path().join("tool.backup");
+        fs::write(&source, "binary").unwrap();
+
+        link_file(&source, &link).unwrap();
+        fs::write(&source, "changed").unwrap();
+        assert_eq!(fs::read_to_string(&link).unwrap(), "changed");
+
+        fs::remove_file(&source).unwrap();
+        rename_file(&link, &source).unwrap();
+        assert!(source.exists());
+        assert!(!link.exists());
+    }
+
+    #[test]
+    fn executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tool");
+        fs::write(&path, "binary").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        set_executable(&path).unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+    }
+
+    #[test]
+    fn invalid_operation() {
+        assert!(run_file_operation("chmod", &[PathBuf::from("/tmp/grpm")]).is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    fmt,
+    io::{self, BufRead},
+};
+
+use crate::{
+    print,
+    repo::{AssetInfo, SearchResult},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Cancelled,
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Cancelled => write!(f, "Cancelled by user"),
+            Error::Io(error) => write!(f, "Could not read answer: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+#[derive(PartialEq, Debug)]
+enum Step {
+    Selected(usize),
+    Cancel,
+    Show,
+    Retry(String),
+}
+
+/// State of a list prompt. Entries are identified by their index in `names`,
+/// typing text instead of an index narrows the shown entries down.
+struct ListPrompt<'a> {
+    names: &'a [&'a str],
+    visible: Vec<usize>,
+}
+
+impl<'a> ListPrompt<'a> {
+    fn new(names: &'a [&'a str]) -> ListPrompt<'a> {
+        ListPrompt {
+            names,
+            visible: (0..names.len()).collect(),
+        }
+    }
+
+    fn answer(&mut self, input: &str) -> Step {
+        let input = input.trim();
+        if input.is_empty() {
+            return Step::Retry("Please enter a number, a filter or q to cancel".to_string());
+        }
+        if input.eq_ignore_ascii_case("q") {
+            return Step::Cancel;
+        }
+        if let Ok(index) = input.parse::<usize>() {
+            if self.visible.contains(&index) {
+                return Step::Selected(index);
+            }
+            return Step::Retry(format!("{} is not one of the listed numbers", index));
+        }
+
+        let matches: Vec<usize> = (0..self.names.len())
+            .filter(|index| fuzzy_matches(input, self.names[*index]))
+            .collect();
+        if matches.is_empty() {
+            return Step::Retry(format!("Nothing matches '{}'", input));
+        }
+        self.visible = matches;
+        Step::Show
+    }
+}
+
+/// Returns true if all characters of `query` appear in `name` in the same order, ignoring case.
+pub fn fuzzy_matches(query: &str, name: &str) -> bool {
+    let mut name_chars = name.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| !c.is_whitespace())
+        .all(|query_char| name_chars.any(|name_char| name_char == query_char))
+}
+
+/// Asks the user to choose one of the assets and returns its index.
+pub fn choose_asset(assets: &[&AssetInfo], question: &str) -> Result<usize, Error> {
+    let names: Vec<&str> = assets.iter().map(|asset| asset.name.as_str()).collect();
+    choose(&names, question, |visible| {
+        let visible_assets: Vec<(usize, &AssetInfo)> = visible
+            .iter()
+            .map(|index| (*index, assets[*index]))
+            .collect();
+        print::print_assets(&visible_assets);
+    })
+}
+
+/// Asks the user to choose one of the search results and returns its index.
+pub fn choose_search_result(results: &[SearchResult], question: &str) -> Result<usize, Error> {
+    let names: Vec<&str> = results
+        .iter()
+        .map(|result| result.full_name.as_str())
+        .collect();
+    choose(&names, question, |visible| {
+        let visible_results: Vec<(usize, &SearchResult)> = visible
+            .iter()
+            .map(|index| (*index, &results[*index]))
+            .collect();
+        print::print_search_choices(&visible_results);
+    })
+}
+
+fn choose(names: &[&str], question: &str, show: impl Fn(&[usize])) -> Result<usize, Error> {
+    let mut prompt = ListPrompt::new(names);
+
+    show(&prompt.visible);
+    loop {
+        let input = ask(&format!("{} (number, filter or q to cancel)", question))?;
+        match prompt.answer(&input) {
+            Step::Selected(index) => return Ok(index),
+            Step::Cancel => return Err(Error::Cancelled),
+            Step::Show => show(&prompt.visible),
+            Step::Retry(message) => print::status!("{}", message),
+        }
+    }
+}
+
+/// Asks a yes/no question, everything but yes cancels.
+pub fn confirm(question: &str) -> Result<(), Error> {
+    let input = ask(&format!("{} [y/N]", question))?;
+    if is_yes(&input) {
+        Ok(())
+    } else {
+        Err(Error::Cancelled)
+    }
+}
+
+fn is_yes(input: &str) -> bool {
+    matches!(input.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+fn ask(question: &str) -> Result<String, Error> {
+    print::print_question(question)?;
+
+    let mut input = String::new();
+    if io::stdin().lock().read_line(&mut input)? == 0 {
+        print::status!();
+        return Err(Error::Cancelled);
+    }
+    Ok(input)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const NAMES: [&str; 3] = [
+        "tool-x86_64-apple-darwin.tar.gz",
+        "tool-x86_64-unknown-linux-musl.tar.gz",
+        "tool-x86_64-unknown-linux-musl.sha256sum",
+    ];
+
+    #[test]
+    fn fuzzy() {
+        assert!(fuzzy_matches("linmusl", NAMES[1]));
+        assert!(fuzzy_matches("DARWIN", NAMES[0]));
+        assert!(fuzzy_matches("musl tar", NAMES[1]));
+        assert!(!fuzzy_matches("windows", NAMES[1]));
+        assert!(!fuzzy_matches("lsumin", NAMES[1]));
+    }
+
+    #[test]
+    fn yes() {
+        assert!(is_yes("y\n"));
+        assert!(is_yes("Yes"));
+        assert!(!is_yes("\n"));
+        assert!(!is_yes("no"));
+    }
+
+    #[test]
+    fn select_index() {
+        let mut prompt = ListPrompt::new(&NAMES);
+        assert_eq!(prompt.answer("1\n"), Step::Selected(1));
+    }
+
+    #[test]
+    fn out_of_range() {
+        let mut prompt = ListPrompt::new(&NAMES);
+        assert!(matches!(prompt.answer("3"), Step::Retry(_)));
+    }
+
+    #[test]
+    fn cancel() {
+        let mut prompt = ListPrompt::new(&NAMES);
+        assert_eq!(prompt.answer("q"), Step::Cancel);
+        assert_eq!(prompt.answer("Q\n"), Step::Cancel);
+    }
+
+    #[test]
+    fn empty() {
+        let mut prompt = ListPrompt::new(&NAMES);
+        assert!(matches!(prompt.answer("\n"), Step::Retry(_)));
+    }
+
+    #[test]
+    fn filter() {
+        let mut prompt = ListPrompt::new(&NAMES);
+        assert_eq!(prompt.answer("linux"), Step::Show);
+        assert_eq!(prompt.visible, vec![1, 2]);
+        assert!(matches!(prompt.answer("0"), Step::Retry(_)));
+        assert_eq!(prompt.answer("2"), Step::Selected(2));
+    }
+
+    #[test]
+    fn filter_without_matches() {
+        let mut prompt = ListPrompt::new(&NAMES);
+        assert!(matches!(prompt.answer("windows"), Step::Retry(_)));
+        assert_eq!(prompt.visible, vec![0, 1, 2]);
+    }
+}
@@ -1,20 +1,36 @@
 use std::{
+    fmt,
     fs::File,
-    io::copy,
+    io::{self, copy},
     path::Path,
+    sync::{Mutex, PoisonError},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use attohttpc::{Error, RequestBuilder};
+use attohttpc::{header::HeaderMap, RequestBuilder, Response, StatusCode};
 use octocrab::models::{repos::Release, Repository};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+use crate::{
+    cache::{CachedResponse, HttpCache},
+    parallel, platform,
+};
+
+/// Longest time we are willing to sleep for a rate limit reset before giving up.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+#[derive(Serialize)]
 pub struct RepoInfo {
     pub name: String,
     pub full_name: String,
     pub description: Option<String>,
     pub version: String,
+    pub published_at: Option<String>,
     pub assets: Vec<AssetInfo>,
 }
 
+#[derive(Serialize)]
 pub struct AssetInfo {
     pub name: String,
     pub url: String,
@@ -22,38 +38,352 @@ pub struct AssetInfo {
     pub download_count: i64,
 }
 
-fn build_api_query(url: &str, token: &str) -> RequestBuilder {
-    build_query(url)
-        .bearer_auth(token)
-        .header("ACCEPT", "application/vnd.github+json")
-        .header("X-GitHub-Api-Version", "2022-11-28")
+#[derive(Serialize)]
+pub struct SearchResult {
+    pub full_name: String,
+    pub description: Option<String>,
+    pub stars: u32,
+    pub version: String,
 }
 
-fn build_query(url: &str) -> RequestBuilder {
-    attohttpc::get(url).header("User-Agent", "grpm")
+/// Rate limit of the GitHub API and scopes of the configured token.
+pub struct ApiStatus {
+    /// Only classic tokens report their scopes.
+    pub scopes: Option<Vec<String>>,
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset_in: Duration,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    items: Vec<Repository>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be sent or the response not be read.
+    Network(attohttpc::Error),
+    Json(serde_json::Error),
+    Io(io::Error),
+    NotFound(String),
+    Unauthorized,
+    Forbidden,
+    RateLimited {
+        reset_in: Duration,
+    },
+    Status(StatusCode, String),
+    /// The resource is needed in offline mode but was never fetched.
+    NotCached(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Network(error) => write!(f, "{}", error),
+            Error::Json(error) => write!(f, "Invalid response: {}", error),
+            Error::Io(error) => write!(f, "{}", error),
+            Error::NotFound(resource) => write!(f, "{} not found", resource),
+            Error::Unauthorized => write!(f, "The configured token was rejected by GitHub"),
+            Error::Forbidden => write!(
+                f,
+                "Access denied by GitHub, the rate limit is exceeded or the token lacks permissions"
+            ),
+            Error::RateLimited { reset_in } => write!(
+                f,
+                "GitHub API rate limit exceeded, it resets in {} minute(s)",
+                reset_in.as_secs() / 60 + 1
+            ),
+            Error::Status(status, url) => write!(f, "{} answered with {}", url, status),
+            Error::NotCached(resource) => write!(f, "{} is not cached", resource),
+        }
+    }
+}
+
+impl Error {
+    /// Names the missing resource of a `NotFound` error.
+    fn describe_not_found(self, resource: impl Fn() -> String) -> Error {
+        match self {
+            Error::NotFound(_) => Error::NotFound(resource()),
+            error => error,
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<attohttpc::Error> for Error {
+    fn from(error: attohttpc::Error) -> Self {
+        Error::Network(error)
+    }
 }
 
-fn get_repo(repo: &str, token: &str) -> Result<Repository, Error> {
-    let url = format!("https://api.github.com/repos/{}", repo);
-    let response = build_api_query(&url, token).send()?.error_for_status()?;
-    Ok(response.json()?)
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
 }
 
-fn get_latest_release(repo: &str, token: &str) -> Result<Release, Error> {
-    let url = format!("https://api.github.com/repos/{}/releases/latest", repo);
-    let response = build_api_query(&url, token).send()?.error_for_status()?;
-    Ok(response.json()?)
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Json(error)
+    }
 }
 
-pub fn get_repo_infos(full_name: &str, token: &str) -> Result<RepoInfo, Error> {
-    let repository = get_repo(full_name, token)?;
-    let release = get_latest_release(full_name, token)?;
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct RateLimit {
+    remaining: u64,
+    reset: u64,
+}
+
+impl RateLimit {
+    fn from_headers(headers: &HeaderMap) -> Option<RateLimit> {
+        Some(RateLimit {
+            remaining: header_value(headers, "X-RateLimit-Remaining")?,
+            reset: header_value(headers, "X-RateLimit-Reset")?,
+        })
+    }
+
+    fn reset_in(&self) -> Duration {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Duration::from_secs(self.reset.saturating_sub(now))
+    }
+}
+
+pub struct Client {
+    token: Option<String>,
+    cache: HttpCache,
+    /// Only cached responses are served, nothing is sent.
+    offline: bool,
+    rate_limit: Mutex<Option<RateLimit>>,
+}
+
+impl Client {
+    pub fn new(token: Option<String>, cache: HttpCache, offline: bool) -> Client {
+        Client {
+            token,
+            cache,
+            offline,
+            rate_limit: Mutex::new(None),
+        }
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    pub fn get_repo_infos(&self, full_name: &str) -> Result<RepoInfo, Error> {
+        self.get_repo_infos_for_tag(full_name, None)
+    }
+
+    /// Like `get_repo_infos` but for the release with the given tag instead of the latest one.
+    pub fn get_repo_infos_for_tag(
+        &self,
+        full_name: &str,
+        tag: Option<&str>,
+    ) -> Result<RepoInfo, Error> {
+        let repository = self.get_repo(full_name)?;
+        let release = match tag {
+            Some(tag) => self.get_release_by_tag(full_name, tag)?,
+            None => self.get_latest_release(full_name)?,
+        };
+
+        Ok(create_repo_info(repository, full_name, release))
+    }
+
+    /// Returns the most recent releases, newest first.
+    pub fn get_releases(&self, full_name: &str, limit: usize) -> Result<Vec<RepoInfo>, Error> {
+        let repository = self.get_repo(full_name)?;
+        let url = format!(
+            "https://api.github.com/repos/{}/releases?per_page={}",
+            full_name, limit
+        );
+        let releases: Vec<Release> = self
+            .get_json(&url)
+            .map_err(|error| error.describe_not_found(|| format!("Releases of {}", full_name)))?;
+        Ok(releases
+            .into_iter()
+            .map(|release| create_repo_info(repository.clone(), full_name, release))
+            .collect())
+    }
+
+    /// Searches repositories whose latest release has an asset for this machine.
+    pub fn search(
+        &self,
+        term: &str,
+        limit: usize,
+        jobs: usize,
+    ) -> Result<Vec<SearchResult>, Error> {
+        let url = format!(
+            "https://api.github.com/search/repositories?q={}&per_page={}",
+            encode_query_value(term),
+            limit
+        );
+        let response: SearchResponse = self.get_json(&url)?;
+        let repositories: Vec<(String, Repository)> = response
+            .items
+            .into_iter()
+            .filter_map(|repository| Some((repository.full_name.clone()?, repository)))
+            .collect();
+
+        let releases = parallel::map(&repositories, jobs, |(full_name, _)| {
+            self.get_latest_release(full_name)
+        });
+
+        let mut results = Vec::new();
+        for ((full_name, repository), release) in repositories.into_iter().zip(releases) {
+            let release = match release {
+                Ok(release) => release,
+                Err(Error::NotFound(_)) => continue,
+                Err(error) => return Err(error),
+            };
+            if !release
+                .assets
+                .iter()
+                .any(|asset| platform::matches_host(&asset.name))
+            {
+                continue;
+            }
+            results.push(SearchResult {
+                full_name,
+                description: repository.description,
+                stars: repository.stargazers_count.unwrap_or(0),
+                version: release.tag_name,
+            });
+        }
+        Ok(results)
+    }
+
+    /// Queries the rate limit, which does not count against it and fails if the token is rejected.
+    pub fn get_api_status(&self) -> Result<ApiStatus, Error> {
+        let url = "https://api.github.com/rate_limit";
+        if self.offline {
+            return Err(Error::NotCached(url.to_string()));
+        }
+        let response = build_api_query(url, self.token.as_deref()).send()?;
+        let response = check_status(response, url)?;
+        Ok(api_status_from_headers(response.headers()))
+    }
+
+    fn get_repo(&self, repo: &str) -> Result<Repository, Error> {
+        let url = format!("https://api.github.com/repos/{}", repo);
+        self.get_json(&url)
+            .map_err(|error| error.describe_not_found(|| format!("Repository {}", repo)))
+    }
+
+    fn get_latest_release(&self, repo: &str) -> Result<Release, Error> {
+        let url = format!("https://api.github.com/repos/{}/releases/latest", repo);
+        self.get_json(&url)
+            .map_err(|error| error.describe_not_found(|| format!("A release of {}", repo)))
+    }
+
+    fn get_release_by_tag(&self, repo: &str, tag: &str) -> Result<Release, Error> {
+        let url = format!(
+            "https://api.github.com/repos/{}/releases/tags/{}",
+            repo, tag
+        );
+        self.get_json(&url)
+            .map_err(|error| error.describe_not_found(|| format!("Release {} of {}", tag, repo)))
+    }
 
-    let repo_info = RepoInfo {
+    /// Fetches an API resource, revalidating a cached copy with `If-None-Match`.
+    /// GitHub does not count `304 Not Modified` answers against the rate limit.
+    fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, Error> {
+        let cached = self.cache.get(url);
+        if self.offline {
+            let cached = cached.ok_or_else(|| Error::NotCached(url.to_string()))?;
+            return Ok(serde_json::from_str(&cached.body)?);
+        }
+        let response = self.send_api_query(url, cached.as_ref().map(|c| c.etag.as_str()))?;
+
+        if let Some(cached) = cached {
+            if response.status() == StatusCode::NOT_MODIFIED {
+                return Ok(serde_json::from_str(&cached.body)?);
+            }
+        }
+
+        let response = check_status(response, url)?;
+        let etag = response
+            .headers()
+            .get("ETag")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = response.text()?;
+        if let Some(etag) = etag {
+            let entry = CachedResponse {
+                etag,
+                body: body.clone(),
+            };
+            if let Err(error) = self.cache.put(url, &entry) {
+                eprintln!("Could not cache response for {}: {}", url, error);
+            }
+        }
+
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    fn send_api_query(&self, url: &str, etag: Option<&str>) -> Result<Response, Error> {
+        self.wait_for_rate_limit()?;
+
+        let mut waited = false;
+        loop {
+            let mut query = build_api_query(url, self.token.as_deref());
+            if let Some(etag) = etag {
+                query = query.header("If-None-Match", etag);
+            }
+            let response = query.send()?;
+
+            let rate_limit = RateLimit::from_headers(response.headers());
+            if rate_limit.is_some() {
+                *self
+                    .rate_limit
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner) = rate_limit;
+            }
+
+            let Some(reset_in) = rate_limited_for(&response, rate_limit) else {
+                return Ok(response);
+            };
+            if waited || reset_in > MAX_RATE_LIMIT_WAIT {
+                return Err(Error::RateLimited { reset_in });
+            }
+            wait(reset_in);
+            waited = true;
+        }
+    }
+
+    /// Avoids sending requests which are known to be rejected because the
+    /// previous response reported an exhausted rate limit.
+    fn wait_for_rate_limit(&self) -> Result<(), Error> {
+        let rate_limit = *self
+            .rate_limit
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(rate_limit) = rate_limit {
+            if rate_limit.remaining == 0 {
+                let reset_in = rate_limit.reset_in();
+                if reset_in > MAX_RATE_LIMIT_WAIT {
+                    return Err(Error::RateLimited { reset_in });
+                }
+                if !reset_in.is_zero() {
+                    wait(reset_in);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn create_repo_info(repository: Repository, full_name: &str, release: Release) -> RepoInfo {
+    RepoInfo {
         name: repository.name,
         full_name: full_name.to_string(),
         description: repository.description,
         version: release.tag_name,
+        published_at: release.published_at.map(|date| date.to_rfc3339()),
         assets: release
             .assets
             .iter()
@@ -64,15 +394,176 @@ pub fn get_repo_infos(full_name: &str, token: &str) -> Result<RepoInfo, Error> {
                 download_count: asset.download_count,
             })
             .collect(),
-    };
+    }
+}
+
+fn build_api_query(url: &str, token: Option<&str>) -> RequestBuilder {
+    let query = build_query(url)
+        .header("ACCEPT", "application/vnd.github+json")
+        .header("X-GitHub-Api-Version", "2022-11-28");
+    match token {
+        Some(token) => query.bearer_auth(token),
+        None => query,
+    }
+}
+
+fn build_query(url: &str) -> RequestBuilder {
+    attohttpc::get(url).header("User-Agent", "grpm")
+}
+
+/// Turns unsuccessful responses into errors, 404, 401 and 403 get their own kind.
+fn check_status(response: Response, url: &str) -> Result<Response, Error> {
+    match response.status() {
+        status if status.is_success() => Ok(response),
+        StatusCode::NOT_FOUND => Err(Error::NotFound(url.to_string())),
+        StatusCode::UNAUTHORIZED => Err(Error::Unauthorized),
+        StatusCode::FORBIDDEN => Err(Error::Forbidden),
+        status => Err(Error::Status(status, url.to_string())),
+    }
+}
 
-    Ok(repo_info)
+fn encode_query_value(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn api_status_from_headers(headers: &HeaderMap) -> ApiStatus {
+    let scopes = headers
+        .get("X-OAuth-Scopes")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|scope| !scope.is_empty())
+                .map(str::to_string)
+                .collect()
+        });
+    let rate_limit = RateLimit::from_headers(headers);
+    ApiStatus {
+        scopes,
+        limit: header_value(headers, "X-RateLimit-Limit").unwrap_or_default(),
+        remaining: rate_limit.map_or(0, |rate_limit| rate_limit.remaining),
+        reset_in: rate_limit.map_or(Duration::ZERO, |rate_limit| rate_limit.reset_in()),
+    }
+}
+
+fn header_value(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+/// Returns how long to wait if the response was rejected by a primary or secondary rate limit.
+fn rate_limited_for(response: &Response, rate_limit: Option<RateLimit>) -> Option<Duration> {
+    let status = response.status();
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    if let Some(retry_after) = header_value(response.headers(), "Retry-After") {
+        return Some(Duration::from_secs(retry_after));
+    }
+    match rate_limit {
+        Some(rate_limit) if rate_limit.remaining == 0 => Some(rate_limit.reset_in()),
+        _ => None,
+    }
+}
+
+fn wait(duration: Duration) {
+    eprintln!(
+        "GitHub API rate limit reached, waiting {} second(s)...",
+        duration.as_secs()
+    );
+    thread::sleep(duration);
 }
 
 pub fn download_asset(asset: &AssetInfo, destination: &Path) -> Result<(), Error> {
-    let mut response = build_query(&asset.url).send()?.error_for_status()?;
-    let mut destination_file_buffer = std::io::BufWriter::new(File::create(destination).unwrap());
-    copy(&mut response, &mut destination_file_buffer).unwrap();
+    let response = build_query(&asset.url).send()?;
+    let mut response = check_status(response, &asset.url)
+        .map_err(|error| error.describe_not_found(|| format!("Asset {}", asset.name)))?;
+    let mut destination_file_buffer = io::BufWriter::new(File::create(destination)?);
+    copy(&mut response, &mut destination_file_buffer)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn rate_limit_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-RateLimit-Remaining", "42".parse().unwrap());
+        headers.insert("X-RateLimit-Reset", "1700000000".parse().unwrap());
+        let rate_limit = RateLimit::from_headers(&headers);
+        assert_eq!(
+            rate_limit,
+            Some(RateLimit {
+                remaining: 42,
+                reset: 1700000000
+            })
+        );
+    }
+
+    #[test]
+    fn api_status() {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-OAuth-Scopes", "repo, read:org".parse().unwrap());
+        headers.insert("X-RateLimit-Limit", "5000".parse().unwrap());
+        headers.insert("X-RateLimit-Remaining", "4999".parse().unwrap());
+        headers.insert("X-RateLimit-Reset", "0".parse().unwrap());
+        let status = api_status_from_headers(&headers);
+        assert_eq!(
+            status.scopes,
+            Some(vec!["repo".to_string(), "read:org".to_string()])
+        );
+        assert_eq!(status.limit, 5000);
+        assert_eq!(status.remaining, 4999);
+
+        let status = api_status_from_headers(&HeaderMap::new());
+        assert!(status.scopes.is_none());
+    }
+
+    #[test]
+    fn rate_limit_missing_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-RateLimit-Remaining", "42".parse().unwrap());
+        assert!(RateLimit::from_headers(&headers).is_none());
+    }
+
+    #[test]
+    fn offline_without_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Client::new(None, HttpCache::new(dir.path()), true);
+        assert!(matches!(
+            client.get_repo_infos("owner/repo"),
+            Err(Error::NotCached(url)) if url == "https://api.github.com/repos/owner/repo"
+        ));
+    }
+
+    #[test]
+    fn encode_query() {
+        assert_eq!(encode_query_value("ripgrep"), "ripgrep");
+        assert_eq!(encode_query_value("fuzzy finder"), "fuzzy%20finder");
+        assert_eq!(
+            encode_query_value("language:rust&stars:>100"),
+            "language%3Arust%26stars%3A%3E100"
+        );
+    }
+
+    #[test]
+    fn rate_limit_reset_in_past() {
+        let rate_limit = RateLimit {
+            remaining: 0,
+            reset: 0,
+        };
+        assert_eq!(rate_limit.reset_in(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    env,
+    ffi::OsStr,
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+/// Directories of `$PATH` in lookup order.
+pub fn directories() -> Vec<PathBuf> {
+    env::var_os("PATH")
+        .map(|path| split(&path))
+        .unwrap_or_default()
+}
+
+fn split(path: &OsStr) -> Vec<PathBuf> {
+    env::split_paths(path)
+        .filter(|directory| !directory.as_os_str().is_empty())
+        .collect()
+}
+
+fn same_directory(first: &Path, second: &Path) -> bool {
+    match (fs::canonicalize(first), fs::canonicalize(second)) {
+        (Ok(first), Ok(second)) => first == second,
+        _ => first == second,
+    }
+}
+
+/// Returns true for regular files with an executable bit.
+pub fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Returns true if the directory is one of the given directories.
+pub fn contains(directories: &[PathBuf], directory: &Path) -> bool {
+    directories
+        .iter()
+        .any(|entry| same_directory(entry, directory))
+}
+
+/// Returns the executable with the same name which is found before the one in the directory.
+pub fn find_shadowing(directories: &[PathBuf], directory: &Path, binary: &str) -> Option<PathBuf> {
+    directories
+        .iter()
+        .take_while(|entry| !same_directory(entry, directory))
+        .map(|entry| entry.join(binary))
+        .find(|path| is_executable(path))
+}
+
+/// Warns if an installed binary can not be run by its name.
+pub fn check_reachable(directory: &Path, binary: &str) -> Option<String> {
+    let directories = directories();
+    if !contains(&directories, directory) {
+        return Some(format!(
+            "Warning: {} is not in $PATH, add it to run {} by its name",
+            directory.display(),
+            binary
+        ));
+    }
+    find_shadowing(&directories, directory, binary).map(|shadowing| {
+        format!(
+            "Warning: {} is shadowed by {} which comes first in $PATH",
+            directory.join(binary).display(),
+            shadowing.display()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::executable;
+
+    #[test]
+    fn split_path() {
+        let directories = split(OsStr::new("/usr/bin::/home/user/.local/bin"));
+        assert_eq!(
+            directories,
+            vec![
+                PathBuf::from("/usr/bin"),
+                PathBuf::from("/home/user/.local/bin")
+            ]
+        );
+        assert!(contains(&directories, Path::new("/usr/bin")));
+        assert!(!contains(&directories, Path::new("/opt/bin")));
+    }
+
+    #[test]
+    fn shadowing() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let directories = vec![first.path().to_path_buf(), second.path().to_path_buf()];
+        executable(&second.path().join("rg"), "#!/bin/sh\n");
+        assert_eq!(find_shadowing(&directories, second.path(), "rg"), None);
+
+        // Files without executable bit are not found by the shell
+        fs::write(first.path().join("rg"), "").unwrap();
+        assert_eq!(find_shadowing(&directories, second.path(), "rg"), None);
+
+        executable(&first.path().join("rg"), "#!/bin/sh\n");
+        assert_eq!(
+            find_shadowing(&directories, second.path(), "rg"),
+            Some(first.path().join("rg"))
+        );
+        assert_eq!(find_shadowing(&directories, first.path(), "rg"), None);
+    }
+}
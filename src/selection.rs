@@ -0,0 +1,356 @@
+use std::fmt;
+
+use regex::Regex;
+
+use crate::{
+    platform, prompt,
+    repo::{AssetInfo, SearchResult},
+};
+
+/// Pattern used to select an asset by name.
+/// Patterns enclosed in slashes (`/musl/`) are regular expressions, everything else is a glob.
+pub enum AssetPattern {
+    Glob(glob::Pattern),
+    Regex(Regex),
+}
+
+pub enum AssetSelector {
+    Any,
+    Index(usize),
+    Pattern(AssetPattern),
+    /// Name the previously installed asset has in the new release, see `renamed_asset`.
+    /// Falls back to the assets for this machine if no asset has the name.
+    Previous(String),
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidPattern(String),
+    NoAssets,
+    NoMatch(String),
+    Ambiguous(Vec<String>),
+    NoRepository(String),
+    AmbiguousRepository(Vec<String>),
+    IndexOutOfRange(usize, usize),
+    Prompt(prompt::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidPattern(error) => write!(f, "Invalid asset pattern: {}", error),
+            Error::NoAssets => write!(f, "The release has no assets"),
+            Error::NoMatch(pattern) => write!(f, "No asset matches '{}'", pattern),
+            Error::Ambiguous(names) => write!(
+                f,
+                "Multiple assets match, use --asset or --asset-index to choose one of: {}",
+                names.join(", ")
+            ),
+            Error::NoRepository(term) => {
+                write!(
+                    f,
+                    "No repository with a release for this machine matches '{}'",
+                    term
+                )
+            }
+            Error::AmbiguousRepository(names) => write!(
+                f,
+                "Multiple repositories match, use owner/repository to choose one of: {}",
+                names.join(", ")
+            ),
+            Error::IndexOutOfRange(index, count) => write!(
+                f,
+                "Asset index {} is out of range, the release has {} assets",
+                index, count
+            ),
+            Error::Prompt(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl AssetPattern {
+    pub fn parse(pattern: &str) -> Result<AssetPattern, Error> {
+        let regex = pattern
+            .strip_prefix('/')
+            .and_then(|pattern| pattern.strip_suffix('/'));
+        match regex {
+            Some(regex) => Regex::new(regex)
+                .map(AssetPattern::Regex)
+                .map_err(|error| Error::InvalidPattern(error.to_string())),
+            None => glob::Pattern::new(pattern)
+                .map(AssetPattern::Glob)
+                .map_err(|error| Error::InvalidPattern(error.to_string())),
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            AssetPattern::Glob(glob) => glob.matches(name),
+            AssetPattern::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+impl fmt::Display for AssetPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetPattern::Glob(glob) => write!(f, "{}", glob),
+            AssetPattern::Regex(regex) => write!(f, "/{}/", regex),
+        }
+    }
+}
+
+/// Replaces the version in the name of an asset of the release `old_tag` with the one of `new_tag`,
+/// e.g. *tool-v1.2.3-x86_64.tar.gz* becomes *tool-v1.3.0-x86_64.tar.gz*.
+pub fn renamed_asset(asset: &str, old_tag: &str, new_tag: &str) -> String {
+    let version = |tag: &str| {
+        tag.trim_start_matches(|c: char| !c.is_ascii_digit())
+            .to_string()
+    };
+    if asset.contains(old_tag) {
+        return asset.replacen(old_tag, new_tag, 1);
+    }
+    let (old_version, new_version) = (version(old_tag), version(new_tag));
+    if old_version.is_empty() {
+        return asset.to_string();
+    }
+    asset.replacen(&old_version, &new_version, 1)
+}
+
+/// Returns the index of the selected asset.
+/// If the selection is not unique the user is asked, unless `interactive` is false.
+pub fn select_asset(
+    assets: &[AssetInfo],
+    selector: &AssetSelector,
+    interactive: bool,
+) -> Result<usize, Error> {
+    if assets.is_empty() {
+        return Err(Error::NoAssets);
+    }
+
+    let candidates: Vec<usize> = match selector {
+        AssetSelector::Index(index) if *index < assets.len() => return Ok(*index),
+        AssetSelector::Index(index) => {
+            return Err(Error::IndexOutOfRange(*index, assets.len()));
+        }
+        AssetSelector::Any => (0..assets.len()).collect(),
+        AssetSelector::Previous(name) => {
+            if let Some(index) = assets.iter().position(|asset| asset.name == *name) {
+                return Ok(index);
+            }
+            let candidates: Vec<usize> = (0..assets.len())
+                .filter(|index| platform::matches_host(&assets[*index].name))
+                .collect();
+            if candidates.is_empty() {
+                (0..assets.len()).collect()
+            } else {
+                candidates
+            }
+        }
+        AssetSelector::Pattern(pattern) => {
+            let candidates: Vec<usize> = (0..assets.len())
+                .filter(|index| pattern.matches(&assets[*index].name))
+                .collect();
+            if candidates.is_empty() {
+                return Err(Error::NoMatch(pattern.to_string()));
+            }
+            candidates
+        }
+    };
+
+    if candidates.len() == 1 {
+        return Ok(candidates[0]);
+    }
+
+    if !interactive {
+        let names = candidates
+            .iter()
+            .map(|index| assets[*index].name.to_string())
+            .collect();
+        return Err(Error::Ambiguous(names));
+    }
+
+    let candidate_assets: Vec<&AssetInfo> =
+        candidates.iter().map(|index| &assets[*index]).collect();
+    let choosen_index = prompt::choose_asset(&candidate_assets, "Choose an asset to download")
+        .map_err(Error::Prompt)?;
+    Ok(candidates[choosen_index])
+}
+
+/// Returns the index of the search result to install.
+/// If there are multiple results the user is asked, unless `interactive` is false.
+pub fn select_repository(
+    results: &[SearchResult],
+    term: &str,
+    interactive: bool,
+) -> Result<usize, Error> {
+    match results.len() {
+        0 => Err(Error::NoRepository(term.to_string())),
+        1 => Ok(0),
+        _ if !interactive => Err(Error::AmbiguousRepository(
+            results
+                .iter()
+                .map(|result| result.full_name.to_string())
+                .collect(),
+        )),
+        _ => prompt::choose_search_result(results, "Choose a repository to install")
+            .map_err(Error::Prompt),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn assets(names: &[&str]) -> Vec<AssetInfo> {
+        names
+            .iter()
+            .map(|name| AssetInfo {
+                name: name.to_string(),
+                url: format!("https://example.com/{}", name),
+                size: 0,
+                download_count: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn renamed() {
+        assert_eq!(
+            renamed_asset("tool-v1.2.3-x86_64.tar.gz", "v1.2.3", "v1.3.0"),
+            "tool-v1.3.0-x86_64.tar.gz"
+        );
+        assert_eq!(
+            renamed_asset("tool-1.2.3-x86_64.tar.gz", "v1.2.3", "v1.3.0"),
+            "tool-1.3.0-x86_64.tar.gz"
+        );
+        assert_eq!(
+            renamed_asset("tool-x86_64.tar.gz", "v1.2.3", "v1.3.0"),
+            "tool-x86_64.tar.gz"
+        );
+    }
+
+    #[test]
+    fn previous_asset() {
+        let assets = assets(&[
+            "tool-v1.3.0-x86_64-unknown-linux-gnu.tar.gz",
+            "tool-v1.3.0-x86_64-unknown-linux-musl.tar.gz",
+            "tool-v1.3.0-x86_64-unknown-linux-musl.tar.gz.sha256",
+        ]);
+        let selector =
+            AssetSelector::Previous("tool-v1.3.0-x86_64-unknown-linux-musl.tar.gz".to_string());
+        assert_eq!(select_asset(&assets, &selector, false).unwrap(), 1);
+
+        // Without a build for this machine every asset is a candidate
+        let assets = self::assets(&["tool-a.zip", "tool-b.zip"]);
+        let selector = AssetSelector::Previous("tool.zip".to_string());
+        assert!(matches!(
+            select_asset(&assets, &selector, false),
+            Err(Error::Ambiguous(_))
+        ));
+    }
+
+    #[test]
+    fn glob_pattern() {
+        let pattern = AssetPattern::parse("*x86_64*linux*.tar.gz").unwrap();
+        assert!(pattern.matches("tool-x86_64-unknown-linux-musl.tar.gz"));
+        assert!(!pattern.matches("tool-x86_64-unknown-linux-musl.sha256sum"));
+    }
+
+    #[test]
+    fn regex_pattern() {
+        let pattern = AssetPattern::parse("/linux-(musl|gnu)\\.tar/").unwrap();
+        assert!(pattern.matches("tool-x86_64-unknown-linux-musl.tar.gz"));
+        assert!(!pattern.matches("tool-x86_64-apple-darwin.tar.gz"));
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        assert!(AssetPattern::parse("/(/").is_err());
+    }
+
+    #[test]
+    fn select_by_pattern() {
+        let assets = assets(&[
+            "tool-darwin.tar.gz",
+            "tool-linux.tar.gz",
+            "tool-linux.sha256",
+        ]);
+        let pattern = AssetPattern::parse("*linux.tar.gz").unwrap();
+        let index = select_asset(&assets, &AssetSelector::Pattern(pattern), false).unwrap();
+        assert_eq!(index, 1);
+    }
+
+    #[test]
+    fn select_ambiguous() {
+        let assets = assets(&["tool-linux.tar.gz", "tool-linux.sha256"]);
+        let pattern = AssetPattern::parse("tool-linux*").unwrap();
+        let result = select_asset(&assets, &AssetSelector::Pattern(pattern), false);
+        assert!(matches!(result, Err(Error::Ambiguous(names)) if names.len() == 2));
+    }
+
+    #[test]
+    fn select_no_match() {
+        let assets = assets(&["tool-linux.tar.gz"]);
+        let pattern = AssetPattern::parse("*windows*").unwrap();
+        let result = select_asset(&assets, &AssetSelector::Pattern(pattern), false);
+        assert!(matches!(result, Err(Error::NoMatch(_))));
+    }
+
+    #[test]
+    fn select_by_index() {
+        let assets = assets(&["tool-darwin.tar.gz", "tool-linux.tar.gz"]);
+        assert_eq!(
+            select_asset(&assets, &AssetSelector::Index(1), false).unwrap(),
+            1
+        );
+        let result = select_asset(&assets, &AssetSelector::Index(2), false);
+        assert!(matches!(result, Err(Error::IndexOutOfRange(2, 2))));
+    }
+
+    #[test]
+    fn select_single_asset() {
+        let assets = assets(&["tool-linux.tar.gz"]);
+        assert_eq!(
+            select_asset(&assets, &AssetSelector::Any, false).unwrap(),
+            0
+        );
+    }
+
+    fn search_results(names: &[&str]) -> Vec<SearchResult> {
+        names
+            .iter()
+            .map(|name| SearchResult {
+                full_name: name.to_string(),
+                description: None,
+                stars: 0,
+                version: "v1.0.0".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn select_repository_unique() {
+        let results = search_results(&["sharkdp/bat"]);
+        assert_eq!(select_repository(&results, "bat", false).unwrap(), 0);
+    }
+
+    #[test]
+    fn select_repository_ambiguous() {
+        let results = search_results(&["sharkdp/bat", "astaxie/bat"]);
+        let result = select_repository(&results, "bat", false);
+        assert!(matches!(result, Err(Error::AmbiguousRepository(names)) if names.len() == 2));
+        let result = select_repository(&[], "bat", false);
+        assert!(matches!(result, Err(Error::NoRepository(_))));
+    }
+
+    #[test]
+    fn select_any_ambiguous() {
+        let assets = assets(&["tool-darwin.tar.gz", "tool-linux.tar.gz"]);
+        let result = select_asset(&assets, &AssetSelector::Any, false);
+        assert!(matches!(result, Err(Error::Ambiguous(_))));
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use tempfile::TempDir;
+
+use crate::privilege;
+
+/// Returns true for staging directories, temporary copies and backups which an
+/// interrupted installation left behind.
+pub fn is_leftover(file_name: &str) -> bool {
+    file_name.starts_with(".grpm-") || file_name.ends_with(".grpm-backup")
+}
+
+/// Creates the directory archives are extracted to before they are moved into place.
+/// It lives inside the install directory if possible, so placing a file is a rename on the same filesystem.
+pub fn create_staging_dir(install_path: &Path) -> io::Result<TempDir> {
+    let builder = tempfile::Builder::new().prefix(".grpm-staging-").to_owned();
+    if privilege::is_writable(install_path) {
+        fs::create_dir_all(install_path)?;
+        return builder.tempdir_in(install_path);
+    }
+    builder.tempdir()
+}
+
+struct PlacedFile {
+    destination: PathBuf,
+    backup: Option<PathBuf>,
+}
+
+/// Files moved into place by an installation.
+/// Replaced files are kept as backup until the transaction is committed,
+/// a transaction which is dropped without commit is rolled back.
+#[derive(Default)]
+pub struct FileTransaction {
+    placed: Vec<PlacedFile>,
+    finished: bool,
+}
+
+impl FileTransaction {
+    /// Moves the staged file to `destination`. An existing file is replaced atomically,
+    /// so a running binary keeps working and the path never points to a partial file.
+    pub fn place(&mut self, staged: &Path, destination: &Path) -> Result<(), privilege::Error> {
+        let backup = if destination.exists() {
+            let backup = backup_path(destination);
+            privilege::link_file(destination, &backup)?;
+            Some(backup)
+        } else {
+            None
+        };
+
+        if let Err(error) = privilege::place_file(staged, destination) {
+            if let Some(backup) = backup {
+                let _ = privilege::remove_file(&backup);
+            }
+            return Err(error);
+        }
+        self.placed.push(PlacedFile {
+            destination: destination.to_path_buf(),
+            backup,
+        });
+        Ok(())
+    }
+
+    /// Keeps the placed files and removes the backups of the replaced ones.
+    pub fn commit(mut self) {
+        self.finished = true;
+        for placed in &self.placed {
+            if let Some(backup) = &placed.backup {
+                if let Err(error) = privilege::remove_file(backup) {
+                    eprintln!("Could not remove backup {}: {}", backup.display(), error);
+                }
+            }
+        }
+    }
+
+    /// Restores the replaced files and removes the newly placed ones.
+    pub fn rollback(&mut self) {
+        self.finished = true;
+        for placed in self.placed.drain(..).rev() {
+            let result = match &placed.backup {
+                Some(backup) => privilege::rename_file(backup, &placed.destination),
+                None => privilege::remove_file(&placed.destination),
+            };
+            if let Err(error) = result {
+                eprintln!(
+                    "Could not roll back {}: {}",
+                    placed.destination.display(),
+                    error
+                );
+            }
+        }
+    }
+}
+
+impl Drop for FileTransaction {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.rollback();
+        }
+    }
+}
+
+fn backup_path(destination: &Path) -> PathBuf {
+    let file_name = destination
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    destination.with_file_name(format!(".{}.grpm-backup", file_name))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn stage(dir: &Path, content: &str) -> PathBuf {
+        let staged = dir.join("staged");
+        fs::write(&staged, content).unwrap();
+        staged
+    }
+
+    #[test]
+    fn staging_dir_in_install_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let install_path = dir.path().join("bin");
+        let staging_dir = create_staging_dir(&install_path).unwrap();
+        assert_eq!(staging_dir.path().parent().unwrap(), install_path);
+    }
+
+    #[test]
+    fn commit_new_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("tool");
+
+        let mut transaction = FileTransaction::default();
+        transaction
+            .place(&stage(dir.path(), "new"), &destination)
+            .unwrap();
+        transaction.commit();
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "new");
+    }
+
+    #[test]
+    fn commit_replaced_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("tool");
+        fs::write(&destination, "old").unwrap();
+
+        let mut transaction = FileTransaction::default();
+        transaction
+            .place(&stage(dir.path(), "new"), &destination)
+            .unwrap();
+        assert!(backup_path(&destination).exists());
+        transaction.commit();
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "new");
+        assert!(!backup_path(&destination).exists());
+    }
+
+    #[test]
+    fn rollback_new_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("tool");
+
+        let mut transaction = FileTransaction::default();
+        transaction
+            .place(&stage(dir.path(), "new"), &destination)
+            .unwrap();
+        transaction.rollback();
+        assert!(!destination.exists());
+    }
+
+    #[test]
+    fn rollback_on_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("tool");
+        fs::write(&destination, "old").unwrap();
+
+        {
+            let mut transaction = FileTransaction::default();
+            transaction
+                .place(&stage(dir.path(), "new"), &destination)
+                .unwrap();
+        }
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "old");
+        assert!(!backup_path(&destination).exists());
+    }
+
+    #[test]
+    fn failed_place_keeps_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("tool");
+        fs::write(&destination, "old").unwrap();
+
+        let mut transaction = FileTransaction::default();
+        assert!(transaction
+            .place(&dir.path().join("missing"), &destination)
+            .is_err());
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "old");
+        assert!(!backup_path(&destination).exists());
+    }
+
+    #[test]
+    fn leftovers() {
+        assert!(is_leftover(".grpm-staging-a1b2"));
+        let backup = backup_path(Path::new("/bin/rg"));
+        assert!(is_leftover(backup.file_name().unwrap().to_str().unwrap()));
+        assert!(!is_leftover("rg"));
+        assert!(!is_leftover(".grpm"));
+    }
+}
@@ -0,0 +1,29 @@
+//! Helpers shared by the tests of several modules.
+
+use std::{collections::BTreeMap, fs, os::unix::fs::PermissionsExt, path::Path};
+
+use crate::database::Package;
+
+/// Package *owner/\<name\>* v1.0.0 with the binary `<name>` in `dir`, other fields are empty.
+pub fn package(name: &str, dir: &Path) -> Package {
+    Package {
+        name: name.to_string(),
+        full_name: format!("owner/{}", name),
+        version: "v1.0.0".to_string(),
+        binary: name.to_string(),
+        path: dir.to_string_lossy().to_string(),
+        asset: None,
+        url: None,
+        sha256: None,
+        source: None,
+        strip_components: 0,
+        files: Vec::new(),
+        checksums: BTreeMap::new(),
+    }
+}
+
+/// Writes a file with the executable bits set.
+pub fn executable(path: &Path, content: &str) {
+    fs::write(path, content).unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+}
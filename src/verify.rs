@@ -0,0 +1,312 @@
+use serde::Serialize;
+use std::{ffi::OsStr, fmt, path::Path};
+
+use tempfile::NamedTempFile;
+
+use crate::{archive, database::Package, hash, repo};
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    /// The file differs from the one which was installed.
+    Modified,
+    Missing,
+    /// The file has a recorded checksum but could not be read.
+    Unreadable,
+    /// The installation differs from the release asset on GitHub.
+    Mismatch,
+    /// Nothing to compare with, the package was installed by an older version.
+    Unverified,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Ok => write!(f, "ok"),
+            Status::Modified => write!(f, "modified"),
+            Status::Missing => write!(f, "missing"),
+            Status::Unreadable => write!(f, "unreadable"),
+            Status::Mismatch => write!(f, "mismatch"),
+            Status::Unverified => write!(f, "unverified"),
+        }
+    }
+}
+
+/// Result of verifying a single file of a package.
+#[derive(Serialize, Debug)]
+pub struct FileReport {
+    pub package: String,
+    pub path: String,
+    pub status: Status,
+    pub details: Option<String>,
+}
+
+impl FileReport {
+    fn new(package: &Package, path: &str, status: Status, details: Option<String>) -> FileReport {
+        FileReport {
+            package: package.name.to_string(),
+            path: path.to_string(),
+            status,
+            details,
+        }
+    }
+
+    /// Returns true if the file is not known to be installed as released.
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self.status,
+            Status::Modified | Status::Missing | Status::Unreadable | Status::Mismatch
+        )
+    }
+}
+
+fn binary_path(package: &Package) -> String {
+    Path::new(&package.path)
+        .join(&package.binary)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Hashes the binary and further files of a package and compares them with the
+/// checksums recorded at installation.
+pub fn verify_files(package: &Package) -> Vec<FileReport> {
+    let binary_path = binary_path(package);
+    std::iter::once(&binary_path)
+        .chain(&package.files)
+        .map(|path| {
+            if !Path::new(path).exists() {
+                return FileReport::new(package, path, Status::Missing, None);
+            }
+            let Some(expected) = package.checksums.get(path) else {
+                return FileReport::new(
+                    package,
+                    path,
+                    Status::Unverified,
+                    Some("No checksum recorded, reinstall the package".to_string()),
+                );
+            };
+            match hash::sha256_file(Path::new(path)) {
+                Ok(actual) if actual == *expected => {
+                    FileReport::new(package, path, Status::Ok, None)
+                }
+                Ok(actual) => FileReport::new(
+                    package,
+                    path,
+                    Status::Modified,
+                    Some(format!("Expected {}, found {}", expected, actual)),
+                ),
+                Err(error) => {
+                    FileReport::new(package, path, Status::Unreadable, Some(error.to_string()))
+                }
+            }
+        })
+        .collect()
+}
+
+/// Reports the binary of a package whose asset could not be downloaded again.
+pub fn download_failed(package: &Package, error: &repo::Error) -> FileReport {
+    FileReport::new(
+        package,
+        &binary_path(package),
+        Status::Unverified,
+        Some(format!(
+            "Could not download {}: {}",
+            package.asset.as_deref().unwrap_or_default(),
+            error
+        )),
+    )
+}
+
+/// Compares the installed binary with the one in the freshly downloaded release asset.
+/// The asset itself must match the checksum recorded at installation.
+pub fn verify_upstream(package: &Package, download_path: &Path) -> FileReport {
+    let binary_path = binary_path(package);
+    let asset = package.asset.as_deref().unwrap_or_default();
+    if let Some(expected) = &package.sha256 {
+        match hash::sha256_file(download_path) {
+            Ok(actual) if actual == *expected => {}
+            Ok(actual) => {
+                return FileReport::new(
+                    package,
+                    &binary_path,
+                    Status::Mismatch,
+                    Some(format!(
+                        "Asset {} changed upstream, expected {}, found {}",
+                        asset, expected, actual
+                    )),
+                )
+            }
+            Err(error) => {
+                return FileReport::new(
+                    package,
+                    &binary_path,
+                    Status::Unverified,
+                    Some(error.to_string()),
+                )
+            }
+        }
+    }
+
+    let installed = hash::sha256_file(Path::new(&binary_path));
+    match (upstream_binary_hash(package, download_path), installed) {
+        (Ok(upstream), Ok(installed)) if upstream == installed => {
+            FileReport::new(package, &binary_path, Status::Ok, None)
+        }
+        (Ok(_), Ok(_)) => FileReport::new(
+            package,
+            &binary_path,
+            Status::Mismatch,
+            Some(format!("Differs from the binary in {}", asset)),
+        ),
+        (Err(error), _) => FileReport::new(
+            package,
+            &binary_path,
+            Status::Unverified,
+            Some(format!("Could not unpack {}: {}", asset, error)),
+        ),
+        (_, Err(_)) if !Path::new(&binary_path).exists() => {
+            FileReport::new(package, &binary_path, Status::Missing, None)
+        }
+        (_, Err(error)) => FileReport::new(
+            package,
+            &binary_path,
+            Status::Unreadable,
+            Some(error.to_string()),
+        ),
+    }
+}
+
+fn upstream_binary_hash(package: &Package, download_path: &Path) -> Result<String, archive::Error> {
+    let decompressed_file = NamedTempFile::new()?;
+    archive::decompress_file(download_path, decompressed_file.path())?;
+    let unpack_dir = tempfile::tempdir()?;
+    let unpacked_files = archive::unpacking_archive(
+        decompressed_file.path(),
+        unpack_dir.path(),
+        package.strip_components,
+    )?;
+
+    // Renamed binaries are found by the name they have in the archive
+    let source_name = package
+        .source
+        .as_deref()
+        .and_then(|source| Path::new(source).file_name())
+        .and_then(OsStr::to_str);
+    let names: Vec<&str> = source_name
+        .into_iter()
+        .chain([package.binary.as_str()])
+        .collect();
+    let unpacked_file = archive::select_binary(&unpacked_files, &names)?;
+    Ok(hash::sha256_file(
+        &unpack_dir.path().join(&unpacked_file.path),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util;
+    use flate2::{write::GzEncoder, Compression};
+    use std::fs;
+
+    fn package(dir: &Path) -> Package {
+        Package {
+            asset: Some("rg.tar.gz".to_string()),
+            source: Some("ripgrep/rg".to_string()),
+            ..test_util::package("rg", dir)
+        }
+    }
+
+    fn asset(path: &Path, content: &[u8]) {
+        let encoder = GzEncoder::new(fs::File::create(path).unwrap(), Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "ripgrep/rg", content)
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut package = package(dir.path());
+        let binary_path = dir.path().join("rg");
+        fs::write(&binary_path, "binary").unwrap();
+        package.files.push("/missing/_rg".to_string());
+
+        let reports = verify_files(&package);
+        let statuses: Vec<Status> = reports.iter().map(|report| report.status).collect();
+        assert_eq!(statuses, vec![Status::Unverified, Status::Missing]);
+
+        package.checksums.insert(
+            binary_path.to_string_lossy().to_string(),
+            hash::sha256_file(&binary_path).unwrap(),
+        );
+        assert_eq!(verify_files(&package)[0].status, Status::Ok);
+
+        fs::write(&binary_path, "tampered").unwrap();
+        let report = &verify_files(&package)[0];
+        assert_eq!(report.status, Status::Modified);
+        assert!(report.is_failure());
+    }
+
+    #[test]
+    fn unreadable_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut package = package(dir.path());
+        let binary_path = dir.path().join("rg");
+        fs::create_dir(&binary_path).unwrap();
+        package
+            .checksums
+            .insert(binary_path.to_string_lossy().to_string(), "0".repeat(64));
+        let report = &verify_files(&package)[0];
+        assert_eq!(report.status, Status::Unreadable);
+        assert!(report.is_failure());
+    }
+
+    #[test]
+    fn upstream() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut package = package(dir.path());
+        let download_path = dir.path().join("rg.tar.gz");
+        asset(&download_path, b"binary");
+        fs::write(dir.path().join("rg"), "binary").unwrap();
+        assert_eq!(verify_upstream(&package, &download_path).status, Status::Ok);
+
+        fs::write(dir.path().join("rg"), "tampered").unwrap();
+        assert_eq!(
+            verify_upstream(&package, &download_path).status,
+            Status::Mismatch
+        );
+
+        fs::remove_file(dir.path().join("rg")).unwrap();
+        fs::create_dir(dir.path().join("rg")).unwrap();
+        assert_eq!(
+            verify_upstream(&package, &download_path).status,
+            Status::Unreadable
+        );
+        fs::remove_dir(dir.path().join("rg")).unwrap();
+        assert_eq!(
+            verify_upstream(&package, &download_path).status,
+            Status::Missing
+        );
+
+        let report = download_failed(&package, &repo::Error::Unauthorized);
+        assert_eq!(report.status, Status::Unverified);
+        assert!(report
+            .details
+            .unwrap()
+            .starts_with("Could not download rg.tar.gz"));
+
+        package.sha256 = Some("0".repeat(64));
+        let report = verify_upstream(&package, &download_path);
+        assert_eq!(report.status, Status::Mismatch);
+        assert!(report.details.unwrap().contains("changed upstream"));
+    }
+}
@@ -0,0 +1,245 @@
+use std::{cmp::Ordering, fmt};
+
+/// Kind of change between two versions.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Delta {
+    Major,
+    Minor,
+    Patch,
+    Unknown,
+}
+
+impl fmt::Display for Delta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let delta = match self {
+            Delta::Major => "major",
+            Delta::Minor => "minor",
+            Delta::Patch => "patch",
+            Delta::Unknown => "unknown",
+        };
+        write!(f, "{}", delta)
+    }
+}
+
+/// Extracts the numeric components of a release tag like `v1.2.3`, `release-1.2` or `1.2.3-rc1`.
+fn parse(tag: &str) -> Option<Vec<u64>> {
+    let (numbers, _) = split(tag)?;
+    numbers
+        .split('.')
+        .filter(|component| !component.is_empty())
+        .map(|component| component.parse().ok())
+        .collect()
+}
+
+/// Splits a tag into its numeric part and the suffix after it, `1.2.3-rc1` into `1.2.3` and `rc1`.
+fn split(tag: &str) -> Option<(&str, &str)> {
+    let version = from_first_digit(tag)?;
+    let end = version
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(version.len());
+    let suffix = version[end..].trim_start_matches(['-', '+', '_', '.']);
+    Some((&version[..end], suffix))
+}
+
+/// Returns true if `latest` is newer than `current`.
+/// A release is newer than its own prerelease, tags which only differ before the
+/// version like `v1.2.3` and `1.2.3` are the same. Tags which can not be compared
+/// are considered newer if they differ.
+pub fn is_newer(current: &str, latest: &str) -> bool {
+    match compare_tags(current, latest) {
+        Some(ordering) => ordering == Ordering::Less,
+        None => current != latest,
+    }
+}
+
+/// Orders two tags by their version and prerelease suffix, `None` if one of them has no version.
+pub fn compare_tags(left: &str, right: &str) -> Option<Ordering> {
+    let (left_version, right_version) = (parse(left)?, parse(right)?);
+    let suffix = |tag| split(tag).map_or("", |(_, suffix)| suffix);
+    Some(
+        compare(&left_version, &right_version)
+            .then_with(|| compare_suffix(suffix(left), suffix(right))),
+    )
+}
+
+pub fn delta(current: &str, latest: &str) -> Delta {
+    let (Some(current), Some(latest)) = (parse(current), parse(latest)) else {
+        return Delta::Unknown;
+    };
+    let component = |version: &[u64], index: usize| version.get(index).copied().unwrap_or(0);
+    if component(&current, 0) != component(&latest, 0) {
+        Delta::Major
+    } else if component(&current, 1) != component(&latest, 1) {
+        Delta::Minor
+    } else if compare(&current, &latest) != Ordering::Equal {
+        Delta::Patch
+    } else {
+        Delta::Unknown
+    }
+}
+
+/// Finds the tag whose version is printed in the output of `--version`, e.g. `ripgrep 13.0.0`.
+/// Exact matches win over numeric ones, so `1.2.3-rc1` is only chosen if it is printed.
+pub fn find_tag<'a>(output: &str, tags: &'a [String]) -> Option<&'a str> {
+    let printed: Vec<&str> = output
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == ')')
+        .filter_map(from_first_digit)
+        .filter(|token| parse(token).is_some_and(|version| version.len() > 1))
+        .collect();
+
+    let exact = tags
+        .iter()
+        .find(|tag| from_first_digit(tag).is_some_and(|version| printed.contains(&version)));
+    let numeric = || {
+        tags.iter().find(|tag| {
+            parse(tag).is_some_and(|version| {
+                printed
+                    .iter()
+                    .filter_map(|token| parse(token))
+                    .any(|printed| compare(&printed, &version) == Ordering::Equal)
+            })
+        })
+    };
+    exact.or_else(numeric).map(String::as_str)
+}
+
+fn from_first_digit(text: &str) -> Option<&str> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    Some(&text[start..])
+}
+
+fn compare(left: &[u64], right: &[u64]) -> Ordering {
+    let length = left.len().max(right.len());
+    (0..length)
+        .map(|index| {
+            let left = left.get(index).copied().unwrap_or(0);
+            let right = right.get(index).copied().unwrap_or(0);
+            left.cmp(&right)
+        })
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Orders prerelease suffixes, no suffix at all is a release and comes last.
+/// Numbers in the suffix are compared numerically, so `rc.10` comes after `rc.9`.
+fn compare_suffix(left: &str, right: &str) -> Ordering {
+    match (left.is_empty(), right.is_empty()) {
+        (true, true) => return Ordering::Equal,
+        (true, false) => return Ordering::Greater,
+        (false, true) => return Ordering::Less,
+        (false, false) => {}
+    }
+    let (left_parts, right_parts) = (suffix_parts(left), suffix_parts(right));
+    left_parts
+        .iter()
+        .zip(&right_parts)
+        .map(
+            |(left, right)| match (left.parse::<u64>(), right.parse::<u64>()) {
+                (Ok(left), Ok(right)) => left.cmp(&right),
+                _ => left.cmp(right),
+            },
+        )
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or_else(|| left_parts.len().cmp(&right_parts.len()))
+}
+
+/// Splits a suffix into runs of digits and letters, separators are dropped.
+fn suffix_parts(suffix: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start: Option<(usize, bool)> = None;
+    for (index, c) in suffix.char_indices() {
+        let digit = c.is_ascii_digit();
+        if let Some((part_start, part_digit)) = start {
+            if c.is_alphanumeric() && digit == part_digit {
+                continue;
+            }
+            parts.push(&suffix[part_start..index]);
+            start = None;
+        }
+        if c.is_alphanumeric() {
+            start = Some((index, digit));
+        }
+    }
+    if let Some((part_start, _)) = start {
+        parts.push(&suffix[part_start..]);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parse_tags() {
+        assert_eq!(parse("v1.2.3"), Some(vec![1, 2, 3]));
+        assert_eq!(parse("release-1.2"), Some(vec![1, 2]));
+        assert_eq!(parse("1.2.3-rc1"), Some(vec![1, 2, 3]));
+        assert_eq!(parse("nightly"), None);
+    }
+
+    #[test]
+    fn newer() {
+        assert!(is_newer("v0.34.4", "v0.35.0"));
+        assert!(is_newer("v0.9.0", "v0.10.0"));
+        assert!(!is_newer("v0.35.0", "v0.34.4"));
+        assert!(!is_newer("v1.2.3", "v1.2.3"));
+        assert!(is_newer("v1.2.3-rc1", "v1.2.3"));
+        assert!(is_newer("nightly-a", "nightly-b"));
+    }
+
+    #[test]
+    fn suffixes() {
+        assert_eq!(suffix_parts("rc.10"), vec!["rc", "10"]);
+        assert_eq!(suffix_parts("beta2-x"), vec!["beta", "2", "x"]);
+    }
+
+    #[test]
+    fn prefix_only_differences() {
+        assert!(!is_newer("v1.2.3", "1.2.3"));
+        assert!(!is_newer("1.2.3", "v1.2.3"));
+        assert!(!is_newer("release-1.2", "v1.2.0"));
+    }
+
+    #[test]
+    fn prereleases() {
+        assert!(!is_newer("v1.2.3", "v1.2.3-rc1"));
+        assert!(is_newer("v1.2.3-rc1", "v1.2.3"));
+        assert!(is_newer("v1.2.3-rc.9", "v1.2.3-rc.10"));
+        assert!(is_newer("v1.2.3-beta", "v1.2.3-rc1"));
+        assert!(!is_newer("v1.2.3-rc1", "1.2.3-rc1"));
+        assert!(is_newer("v1.2.2", "v1.2.3-rc1"));
+    }
+
+    #[test]
+    fn compare_versions() {
+        assert_eq!(compare_tags("v1.2.3", "1.2.3"), Some(Ordering::Equal));
+        assert_eq!(compare_tags("v1.2.3-rc1", "v1.2.3"), Some(Ordering::Less));
+        assert_eq!(compare_tags("v1.10.0", "v1.9.0"), Some(Ordering::Greater));
+        assert_eq!(compare_tags("nightly", "v1.9.0"), None);
+    }
+
+    #[test]
+    fn find_tags() {
+        let tags: Vec<String> = ["v1.3.0-rc1", "v1.2.0", "v1.1.0", "release-0.9"]
+            .iter()
+            .map(|tag| tag.to_string())
+            .collect();
+        assert_eq!(find_tag("tool 1.2.0 (x86_64)", &tags), Some("v1.2.0"));
+        assert_eq!(find_tag("tool v1.3.0-rc1", &tags), Some("v1.3.0-rc1"));
+        assert_eq!(find_tag("tool version 1.1", &tags), Some("v1.1.0"));
+        assert_eq!(find_tag("tool 0.9", &tags), Some("release-0.9"));
+        assert_eq!(find_tag("tool 2.0.0", &tags), None);
+        assert_eq!(find_tag("usage: tool [options]", &tags), None);
+    }
+
+    #[test]
+    fn deltas() {
+        assert_eq!(delta("v1.2.3", "v2.0.0"), Delta::Major);
+        assert_eq!(delta("v1.2.3", "v1.3.0"), Delta::Minor);
+        assert_eq!(delta("v1.2.3", "v1.2.4"), Delta::Patch);
+        assert_eq!(delta("v1.2.3", "v1.2.3.1"), Delta::Patch);
+        assert_eq!(delta("nightly", "v1.2.4"), Delta::Unknown);
+    }
+}